@@ -0,0 +1,20 @@
+use scrypto::prelude::*;
+
+blueprint! {
+    struct MetadataSetter {}
+
+    impl MetadataSetter {
+        pub fn set_metadata(
+            resource_address: ResourceAddress,
+            key: String,
+            value: String,
+            badge: Proof,
+        ) {
+            let mut resource_manager = borrow_resource_manager!(resource_address);
+            let mut metadata = resource_manager.metadata();
+            metadata.insert(key, value);
+            badge.authorize(|| resource_manager.update_metadata(metadata));
+            badge.drop();
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use scrypto::prelude::*;
+
+blueprint! {
+    struct Treasury {
+        /// The vaults holding the funds, by resource
+        vaults: HashMap<ResourceAddress, Vault>,
+    }
+
+    impl Treasury {
+        pub fn instantiate() -> ComponentAddress {
+            Self {
+                vaults: HashMap::new(),
+            }
+            .instantiate()
+            .globalize()
+        }
+
+        pub fn deposit(&mut self, bucket: Bucket) {
+            let resource_address = bucket.resource_address();
+            self.vaults
+                .entry(resource_address)
+                .or_insert_with(|| Vault::new(resource_address))
+                .put(bucket);
+        }
+
+        pub fn deposit_batch(&mut self, buckets: Vec<Bucket>) {
+            for bucket in buckets {
+                self.deposit(bucket);
+            }
+        }
+
+        pub fn dispense(&mut self, resource_address: ResourceAddress, amount: Decimal) -> Bucket {
+            match self.vaults.get_mut(&resource_address) {
+                Some(vault) => vault.take(amount),
+                None => panic!("The treasury holds no {}", resource_address),
+            }
+        }
+
+        pub fn balance(&self, resource_address: ResourceAddress) -> Decimal {
+            match self.vaults.get(&resource_address) {
+                Some(vault) => vault.amount(),
+                None => Decimal::zero(),
+            }
+        }
+    }
+}
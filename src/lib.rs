@@ -8,14 +8,23 @@
 
 extern crate radix_engine;
 extern crate scrypto;
+extern crate transaction_manifest;
 
 //use radix_engine::engine::validate_data;
-use radix_engine::ledger::SubstateStore;
-use radix_engine::model::{Receipt, ValidatedInstruction}; //, ValidatedInstruction};
+use radix_engine::errors::{RuntimeError, TransactionValidationError};
+use radix_engine::ledger::{InMemorySubstateStore, QueryableSubstateStore, SubstateStore};
+use radix_engine::model::{
+    Instruction, NonFungible, Receipt, ResourceContainerError, SignedTransaction,
+    ValidatedInstruction, Vault, VaultError,
+};
 use radix_engine::transaction::*;
 //use sbor::Decode;
 //use scrypto::{prelude::*, component};
+use scrypto::engine::types::VaultId;
 use scrypto::prelude::*;
+use scrypto::values::ScryptoValue;
+use std::collections::BTreeMap;
+use std::fmt;
 
 /// The user account.
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -29,18 +38,375 @@ pub struct User {
 pub struct TestEnv<'l, L: SubstateStore> {
     /// The transaction executioner.
     pub executor: TransactionExecutor<'l, L>,
-    /// The users of the test environment.
-    pub users: HashMap<String, User>,
+    /// The users of the test environment, ordered by name.
+    pub users: BTreeMap<String, User>,
     /// The current user of the test environment.
     pub current_user: Option<User>,
-    /// The test environment packages.
-    pub packages: HashMap<String, PackageAddress>,
+    /// The test environment packages, ordered by name.
+    pub packages: BTreeMap<String, PackageAddress>,
     /// The current package of the test environment.
     pub current_package: Option<PackageAddress>,
+    /// The code of the test environment packages, by package name.
+    pub packages_code: BTreeMap<String, Vec<u8>>,
     /// Storing users private keys of users
     pub users_pk: HashMap<ComponentAddress, EcdsaPrivateKey>,
+    /// The log of executed transactions, if recording is enabled.
+    pub transaction_log: Option<TransactionLog>,
+    /// The receipts of executed transactions, if recording is enabled.
+    pub receipt_history: Option<ReceiptHistory>,
+    /// The account receiving the faucet XRD left over by `fund_xrd`.
+    faucet_sink: Option<ComponentAddress>,
+    /// The most recently submitted transaction.
+    last_transaction: Option<SignedTransaction>,
+    /// Where the next call deposits the resources left on the worktop.
+    deposit_target: Option<DepositTarget>,
+    /// Whether the next transaction is executed with tracing.
+    trace_next: bool,
+    /// The components created through the test environment, in creation order.
+    components: Vec<ComponentAddress>,
+    /// The resources created through the test environment, in creation order.
+    resources: Vec<ResourceAddress>,
+    /// The user co-signing the transactions of the call helpers, if any.
+    fee_payer: Option<User>,
+    /// The additional private keys of the accounts, signing next to their main key.
+    extra_keys: HashMap<ComponentAddress, Vec<EcdsaPrivateKey>>,
+    /// The label recorded with the receipt of the next transaction.
+    next_label: Option<String>,
+    /// The number of transactions executed, including those run by the executor directly.
+    transaction_count: u64,
+    /// The built-in packages published so far, by name.
+    builtin_packages: HashMap<&'static str, PackageAddress>,
 }
 
+/// The fields of a test environment besides its executor, so that its state can be moved
+/// to another executor over the same ledger.
+struct EnvState {
+    users: BTreeMap<String, User>,
+    current_user: Option<User>,
+    packages: BTreeMap<String, PackageAddress>,
+    current_package: Option<PackageAddress>,
+    packages_code: BTreeMap<String, Vec<u8>>,
+    users_pk: HashMap<ComponentAddress, EcdsaPrivateKey>,
+    transaction_log: Option<TransactionLog>,
+    receipt_history: Option<ReceiptHistory>,
+    faucet_sink: Option<ComponentAddress>,
+    last_transaction: Option<SignedTransaction>,
+    deposit_target: Option<DepositTarget>,
+    trace_next: bool,
+    components: Vec<ComponentAddress>,
+    resources: Vec<ResourceAddress>,
+    fee_payer: Option<User>,
+    extra_keys: HashMap<ComponentAddress, Vec<EcdsaPrivateKey>>,
+    next_label: Option<String>,
+    transaction_count: u64,
+    builtin_packages: HashMap<&'static str, PackageAddress>,
+}
+
+impl EnvState {
+    fn new() -> Self {
+        let users: BTreeMap<String, User> = BTreeMap::new();
+        let packages: BTreeMap<String, PackageAddress> = BTreeMap::new();
+        let packages_code: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        // let current_user: HashMap<String, User> = HashMap::new();
+        let users_pk: HashMap<ComponentAddress, EcdsaPrivateKey> = HashMap::new();
+
+        Self {
+            users,
+            current_user: None,
+            packages,
+            current_package: None,
+            packages_code,
+            users_pk,
+            transaction_log: None,
+            receipt_history: None,
+            faucet_sink: None,
+            last_transaction: None,
+            deposit_target: None,
+            trace_next: false,
+            components: Vec::new(),
+            resources: vec![RADIX_TOKEN],
+            fee_payer: None,
+            extra_keys: HashMap::new(),
+            next_label: None,
+            transaction_count: 0,
+            builtin_packages: HashMap::new(),
+        }
+    }
+}
+
+/// Where a call deposits the resources left on the worktop at the end of the transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepositTarget {
+    /// The account of the current user.
+    CurrentUser,
+    /// The given account.
+    Account(ComponentAddress),
+    /// No deposit, so the transaction fails if any resource is left on the worktop.
+    Omit,
+}
+
+trait DepositLeftovers {
+    /// Appends the deposit of the resources left on the worktop.
+    fn deposit_leftovers(
+        &mut self,
+        target: DepositTarget,
+        user_account: ComponentAddress,
+    ) -> &mut Self;
+}
+
+impl DepositLeftovers for TransactionBuilder {
+    fn deposit_leftovers(
+        &mut self,
+        target: DepositTarget,
+        user_account: ComponentAddress,
+    ) -> &mut Self {
+        match target {
+            DepositTarget::CurrentUser => {
+                self.call_method_with_all_resources(user_account, "deposit_batch")
+            }
+            DepositTarget::Account(account) => {
+                self.call_method_with_all_resources(account, "deposit_batch")
+            }
+            DepositTarget::Omit => self,
+        }
+    }
+}
+
+/// Extra steps for the transaction builders of custom manifests.
+pub trait TransactionBuilderExt {
+    /// Deposits every resource left on the worktop to the given account.
+    ///
+    /// A manifest leaving resources on the worktop fails, so custom manifests should end
+    /// with this call.
+    fn deposit_all_to(&mut self, account: ComponentAddress) -> &mut Self;
+}
+
+impl TransactionBuilderExt for TransactionBuilder {
+    fn deposit_all_to(&mut self, account: ComponentAddress) -> &mut Self {
+        self.call_method_with_all_resources(account, "deposit_batch")
+    }
+}
+
+/// The amount of XRD handed out by each `free_xrd` call on the system component.
+const FAUCET_AMOUNT: i64 = 1_000_000;
+
+/// A recorded sequence of signed transactions that can be replayed against another ledger.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionLog {
+    /// The recorded transactions, in execution order.
+    pub transactions: Vec<SignedTransaction>,
+}
+
+impl TransactionLog {
+    /// Returns an empty transaction log.
+    pub fn new() -> Self {
+        Self {
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Returns the number of recorded transactions.
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Returns true if no transaction has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+/// The receipts of the transactions executed by a test environment.
+#[derive(Debug, Default)]
+pub struct ReceiptHistory {
+    /// The recorded receipts, in execution order.
+    pub receipts: Vec<Receipt>,
+    /// The label of each recorded receipt, if its transaction was labeled.
+    pub labels: Vec<Option<String>>,
+    /// The epoch each recorded transaction was executed in.
+    pub epochs: Vec<u64>,
+}
+
+impl ReceiptHistory {
+    /// Returns an empty receipt history.
+    pub fn new() -> Self {
+        Self {
+            receipts: Vec::new(),
+            labels: Vec::new(),
+            epochs: Vec::new(),
+        }
+    }
+
+    /// Returns the recorded receipts with their labels, in execution order.
+    pub fn labeled(&self) -> impl Iterator<Item = (Option<&str>, &Receipt)> {
+        self.labels
+            .iter()
+            .map(|label| label.as_deref())
+            .zip(self.receipts.iter())
+    }
+}
+
+/// The configuration of a resource created with `TestEnv::create_resource`.
+///
+/// Mirrors the blueprint-side `ResourceBuilder`, which can only run inside a component.
+pub struct ResourceConfig {
+    /// The type of the resource.
+    pub resource_type: ResourceType,
+    /// The metadata of the resource.
+    pub metadata: HashMap<String, String>,
+    /// The access rules of the resource methods.
+    pub access_rules: HashMap<ResourceMethodAuthKey, (AccessRule, Mutability)>,
+    /// The initial supply of the resource, if any.
+    pub mint_params: Option<MintParams>,
+}
+
+impl ResourceConfig {
+    /// Returns the configuration of a fungible resource with maximum divisibility,
+    /// no metadata, no access rules and no initial supply.
+    pub fn new() -> Self {
+        Self {
+            resource_type: ResourceType::Fungible {
+                divisibility: DIVISIBILITY_MAXIMUM,
+            },
+            metadata: HashMap::new(),
+            access_rules: HashMap::new(),
+            mint_params: None,
+        }
+    }
+
+    /// Makes the resource fungible with the given divisibility.
+    pub fn divisibility(mut self, divisibility: u8) -> Self {
+        self.resource_type = ResourceType::Fungible { divisibility };
+        self
+    }
+
+    /// Makes the resource non-fungible.
+    pub fn non_fungible(mut self) -> Self {
+        self.resource_type = ResourceType::NonFungible;
+        self
+    }
+
+    /// Adds a metadata entry.
+    pub fn metadata(mut self, name: &str, value: &str) -> Self {
+        self.metadata.insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Sets the access rule of a resource method.
+    pub fn rule(
+        mut self,
+        method: ResourceMethodAuthKey,
+        rule: AccessRule,
+        mutability: Mutability,
+    ) -> Self {
+        self.access_rules.insert(method, (rule, mutability));
+        self
+    }
+
+    /// Sets the rule for minting.
+    pub fn mintable(self, rule: AccessRule, mutability: Mutability) -> Self {
+        self.rule(ResourceMethodAuthKey::Mint, rule, mutability)
+    }
+
+    /// Sets the rule for burning.
+    pub fn burnable(self, rule: AccessRule, mutability: Mutability) -> Self {
+        self.rule(ResourceMethodAuthKey::Burn, rule, mutability)
+    }
+
+    /// Sets the rule for withdrawing.
+    pub fn restrict_withdraw(self, rule: AccessRule, mutability: Mutability) -> Self {
+        self.rule(ResourceMethodAuthKey::Withdraw, rule, mutability)
+    }
+
+    /// Sets the rule for depositing.
+    pub fn restrict_deposit(self, rule: AccessRule, mutability: Mutability) -> Self {
+        self.rule(ResourceMethodAuthKey::Deposit, rule, mutability)
+    }
+
+    /// Sets the rule for updating metadata.
+    pub fn updateable_metadata(self, rule: AccessRule, mutability: Mutability) -> Self {
+        self.rule(ResourceMethodAuthKey::UpdateMetadata, rule, mutability)
+    }
+
+    /// Sets the initial supply of a fungible resource.
+    pub fn initial_supply(mut self, amount: Decimal) -> Self {
+        self.mint_params = Some(MintParams::fungible(amount));
+        self
+    }
+
+    /// Sets the initial supply of a non-fungible resource.
+    pub fn initial_non_fungible_supply<T: NonFungibleData>(
+        mut self,
+        entries: Vec<(NonFungibleId, T)>,
+    ) -> Self {
+        self.mint_params = Some(MintParams::non_fungible(entries));
+        self
+    }
+}
+
+impl Default for ResourceConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Represents an error when looking up the test environment bookkeeping.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestEnvError {
+    /// No user registered under the given name.
+    UnknownUser(String),
+    /// No package registered under the given name.
+    UnknownPackage(String),
+    /// No current user has been set.
+    NoCurrentUser,
+    /// No current package has been set.
+    NoCurrentPackage,
+    /// The private key of the given user is missing.
+    MissingPrivateKey(User),
+    /// No non-fungible with the given id exists in the resource.
+    UnknownNonFungible(ResourceAddress, NonFungibleId),
+    /// The current user holds less of a resource than required.
+    InsufficientBalance {
+        resource: ResourceAddress,
+        required: Decimal,
+        available: Decimal,
+    },
+}
+
+impl fmt::Display for TestEnvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestEnvError::UnknownUser(name) => write!(f, "No user named {:?} found.", name),
+            TestEnvError::UnknownPackage(name) => write!(f, "No package named {:?} found.", name),
+            TestEnvError::NoCurrentUser => write!(
+                f,
+                "no current user set; call create_user or acting_as first"
+            ),
+            TestEnvError::NoCurrentPackage => write!(
+                f,
+                "no current package set; call publish_package or using_package first"
+            ),
+            TestEnvError::MissingPrivateKey(user) => {
+                write!(f, "For some reason there is no private key for {:?}.", user)
+            }
+            TestEnvError::UnknownNonFungible(resource, id) => {
+                write!(f, "No non-fungible {} found in resource {}.", id, resource)
+            }
+            TestEnvError::InsufficientBalance {
+                resource,
+                required,
+                available,
+            } => write!(
+                f,
+                "The current user holds {} of resource {}, but {} is required.",
+                available, resource, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TestEnvError {}
+
 impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// Returns a test environment instance with the following fields:
     ///
@@ -49,6 +415,9 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// * `current_user` - The current user of the test environment.
     /// * `packages` - The test environment packages.
     /// * `current_package` - The current package of the test environment.
+    /// * `packages_code` - The code of the test environment packages.
+    /// * `transaction_log` - The executed transactions, when recording is enabled.
+    /// * `receipt_history` - The receipts of executed transactions, when recording is enabled.
     ///
     /// # Arguments
     ///
@@ -65,46 +434,76 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// let mut env = TestEnv::new(&mut ledger);
     /// ```
     pub fn new(ledger: &'l mut L) -> Self {
-        let executor = TransactionExecutor::new(ledger, false);
-        let users: HashMap<String, User> = HashMap::new();
-        let packages: HashMap<String, PackageAddress> = HashMap::new();
-        // let current_user: HashMap<String, User> = HashMap::new();
-        let users_pk: HashMap<ComponentAddress, EcdsaPrivateKey> = HashMap::new();
-
-        Self {
-            executor,
-            users,
-            current_user: None,
-            packages,
-            current_package: None,
-            users_pk,
-        }
+        Self::with_executor(TransactionExecutor::new(ledger, false))
     }
 
     /// Returns a test environment instance exactly like `new` but with a tracing executor
     pub fn new_with_tracing(ledger: &'l mut L) -> Self {
-        let executor = TransactionExecutor::new(ledger, true);
-        let users: HashMap<String, User> = HashMap::new();
-        let packages: HashMap<String, PackageAddress> = HashMap::new();
-        // let current_user: HashMap<String, User> = HashMap::new();
-        let users_pk: HashMap<ComponentAddress, EcdsaPrivateKey> = HashMap::new();
+        Self::with_executor(TransactionExecutor::new(ledger, true))
+    }
+
+    fn with_executor(executor: TransactionExecutor<'l, L>) -> Self {
+        Self::from_parts(executor, EnvState::new())
+    }
 
+    /// Builds a test environment from an executor and the state of another one.
+    fn from_parts(executor: TransactionExecutor<'l, L>, state: EnvState) -> Self {
         Self {
             executor,
-            users,
-            current_user: None,
-            packages,
-            current_package: None,
-            users_pk,
+            users: state.users,
+            current_user: state.current_user,
+            packages: state.packages,
+            current_package: state.current_package,
+            packages_code: state.packages_code,
+            users_pk: state.users_pk,
+            transaction_log: state.transaction_log,
+            receipt_history: state.receipt_history,
+            faucet_sink: state.faucet_sink,
+            last_transaction: state.last_transaction,
+            deposit_target: state.deposit_target,
+            trace_next: state.trace_next,
+            components: state.components,
+            resources: state.resources,
+            fee_payer: state.fee_payer,
+            extra_keys: state.extra_keys,
+            next_label: state.next_label,
+            transaction_count: state.transaction_count,
+            builtin_packages: state.builtin_packages,
         }
     }
 
-    /// Publishes a given package to the transaction execution ledger.
-    ///
-    /// # Arguments
+    /// Splits a test environment into its executor and the rest of its state.
+    fn into_parts(self) -> (TransactionExecutor<'l, L>, EnvState) {
+        let state = EnvState {
+            users: self.users,
+            current_user: self.current_user,
+            packages: self.packages,
+            current_package: self.current_package,
+            packages_code: self.packages_code,
+            users_pk: self.users_pk,
+            transaction_log: self.transaction_log,
+            receipt_history: self.receipt_history,
+            faucet_sink: self.faucet_sink,
+            last_transaction: self.last_transaction,
+            deposit_target: self.deposit_target,
+            trace_next: self.trace_next,
+            components: self.components,
+            resources: self.resources,
+            fee_payer: self.fee_payer,
+            extra_keys: self.extra_keys,
+            next_label: self.next_label,
+            transaction_count: self.transaction_count,
+            builtin_packages: self.builtin_packages,
+        };
+        (self.executor, state)
+    }
+
+    /// Starts recording every transaction executed by the test environment
+    /// into `transaction_log`.
     ///
-    /// * `name` - The name of the package.
-    /// * `package` - The package as a binary array.
+    /// Only the transactions built by the helpers are recorded. Accounts and packages
+    /// are created by the executor directly, so they must be created again, in the same
+    /// order, before replaying the log against a fresh ledger.
     ///
     /// # Examples
     ///
@@ -116,28 +515,21 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
     ///
-    /// env.publish_package(
-    ///     "package",
-    ///     include_code!("../tests/assets/hello-world", "hello_world")
-    /// );
+    /// env.record_transactions();
+    /// env.create_user("acc1");
+    /// env.create_token(10000.into());
+    ///
+    /// assert_eq!(env.transaction_log.as_ref().unwrap().len(), 1);
     /// ```
-    pub fn publish_package(&mut self, name: &str, package: &[u8]) -> &mut Self {
-        let package_addr = self.executor.publish_package(package).unwrap();
-        self.packages.insert(String::from(name), package_addr);
-
-        //If first package set as default
-        match self.current_package {
-            Some(_) => {}
-            None => self.current_package = Some(package_addr),
+    pub fn record_transactions(&mut self) -> &mut Self {
+        if self.transaction_log.is_none() {
+            self.transaction_log = Some(TransactionLog::new());
         }
         self
     }
 
-    /// Retrieve a test environment package by name.
-    ///
-    /// # Arguments
-    ///
-    /// * `name` - The name of the package.
+    /// Starts keeping the receipt of every transaction executed by the test environment
+    /// into `receipt_history`.
     ///
     /// # Examples
     ///
@@ -149,25 +541,39 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
     ///
-    /// env.publish_package(
-    ///     "package",
-    ///     include_code!("../tests/assets/hello-world", "hello_world")
-    /// );
+    /// env.record_receipts();
+    /// env.create_user("acc1");
+    /// let token = env.create_token(10000.into());
     ///
-    /// let package = env.get_package("package");
+    /// assert_eq!(env.receipts().len(), 1);
+    /// assert_eq!(env.last_receipt().unwrap().new_resource_addresses[0], token);
     /// ```
-    pub fn get_package(&self, name: &str) -> PackageAddress {
-        match self.packages.get(name) {
-            Some(&package) => package,
-            None => panic!("No package named {:?} found.", name),
+    pub fn record_receipts(&mut self) -> &mut Self {
+        if self.receipt_history.is_none() {
+            self.receipt_history = Some(ReceiptHistory::new());
+        }
+        self
+    }
+
+    /// Returns the recorded receipts, or an empty slice if recording is disabled.
+    pub fn receipts(&self) -> &[Receipt] {
+        match &self.receipt_history {
+            Some(history) => &history.receipts,
+            None => &[],
         }
     }
 
-    /// Sets the current package of the test environment.
+    /// Returns the most recently recorded receipt, if any.
+    pub fn last_receipt(&self) -> Option<&Receipt> {
+        self.receipts().last()
+    }
+
+    /// Returns the epoch the recorded transaction at `index` was executed in, or `None`
+    /// if recording is disabled or there is no such receipt.
     ///
     /// # Arguments
     ///
-    /// * `name` - The name of the package.
+    /// * `index` - The index of the receipt in `receipts()`.
     ///
     /// # Examples
     ///
@@ -179,25 +585,23 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
     ///
-    /// env.publish_package(
-    ///     "package",
-    ///     include_code!("../tests/assets/hello-world", "hello_world")
-    /// );
+    /// env.record_receipts();
+    /// env.create_user("acc1");
+    /// env.create_token(10000.into());
     ///
-    /// env.using_package("package");
+    /// assert_eq!(env.receipt_epoch(0), Some(0));
     /// ```
-    pub fn using_package(&mut self, name: &str) -> &mut Self {
-        let package = self.get_package(name);
-        self.current_package = Some(package);
-
-        self
+    pub fn receipt_epoch(&self, index: usize) -> Option<u64> {
+        self.receipt_history
+            .as_ref()
+            .and_then(|history| history.epochs.get(index).copied())
     }
 
-    /// Create a test user.
+    /// Re-executes every transaction of a log in order and returns their receipts.
     ///
     /// # Arguments
     ///
-    /// * `name` - The name of the user.
+    /// * `log` - The transaction log to replay.
     ///
     /// # Examples
     ///
@@ -208,31 +612,41 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     ///
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
+    /// env.record_transactions();
+    /// env.create_user("acc1");
+    /// env.create_token(10000.into());
+    /// let log = env.transaction_log.clone().unwrap();
     ///
-    /// env.create_user("test user");
+    /// let mut other_ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut other_env = TestEnv::new(&mut other_ledger);
+    /// other_env.create_user("acc1");
+    /// let receipts = other_env.replay(&log);
+    /// assert!(receipts[0].result.is_ok());
     /// ```
-    pub fn create_user(&mut self, name: &str) -> User {
-        // public_key, private_key, address
-        let (key, private_key, account) = self.executor.new_account();
-        self.users.insert(String::from(name), User { key, account });
-
-        let usr = User { key, account };
-        //adding users private key to Env
-        self.users_pk.insert(account, private_key);
-
-        //If first user set as default
-        match self.current_user {
-            Some(_) => {}
-            None => self.current_user = Some(usr),
-        }
-        usr
+    pub fn replay(&mut self, log: &TransactionLog) -> Vec<Receipt> {
+        log.transactions
+            .iter()
+            .map(|transaction| self.execute(transaction.clone()))
+            .collect()
     }
 
-    /// Retrieve a test user by name.
+    /// Re-executes a log up to one of its transactions, then executes that transaction
+    /// with tracing, printing its full trace, and returns its receipt.
+    ///
+    /// The log is replayed into this test environment, which must be a fresh one set up
+    /// like the recorded one, as with `replay`, and not the one that recorded the log.
+    /// The transactions before `transaction_index` only rebuild the ledger state the
+    /// transaction ran on: they are not recorded, labeled or counted, and don't become
+    /// the last transaction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this test environment has already executed a transaction.
     ///
     /// # Arguments
     ///
-    /// * `name` - The name of the user.
+    /// * `log`               - The transaction log to replay.
+    /// * `transaction_index` - The index in the log of the transaction to trace.
     ///
     /// # Examples
     ///
@@ -243,23 +657,87 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     ///
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
+    /// env.record_transactions();
+    /// env.create_user("acc1");
+    /// env.create_token(10000.into());
+    /// env.create_token(10.into());
+    /// let log = env.transaction_log.clone().unwrap();
     ///
-    /// env.create_user("test user");
-    ///
-    /// let user = env.get_user("test user");
+    /// let mut other_ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut other_env = TestEnv::new(&mut other_ledger);
+    /// other_env.create_user("acc1");
+    /// let receipt = other_env.debug_replay(&log, 1);
+    /// assert!(receipt.result.is_ok());
     /// ```
-    pub fn get_user(&self, name: &str) -> &User {
-        match self.users.get(name) {
-            Some(user) => user,
-            None => panic!("No user named {:?} found.", name),
+    pub fn debug_replay(&mut self, log: &TransactionLog, transaction_index: usize) -> Receipt {
+        assert!(
+            transaction_index < log.len(),
+            "No transaction {} in a log of {} transactions",
+            transaction_index,
+            log.len()
+        );
+        assert!(
+            self.last_transaction.is_none(),
+            "debug_replay needs a test environment that has executed no transaction yet"
+        );
+        for transaction in &log.transactions[..transaction_index] {
+            let receipt = self.executor.validate_and_execute(transaction).unwrap();
+            self.track_created(&receipt);
         }
+        self.trace_next_call();
+        self.execute(log.transactions[transaction_index].clone())
     }
 
-    /// Set the current user of the test environment.
+    /// Validates and executes a signed transaction, recording it if enabled.
+    fn execute(&mut self, transaction: SignedTransaction) -> Receipt {
+        self.try_execute(transaction).unwrap()
+    }
+
+    /// Validates and executes a signed transaction, returning the validation error if
+    /// it is rejected. Only accepted transactions are recorded.
+    fn try_execute(
+        &mut self,
+        transaction: SignedTransaction,
+    ) -> Result<Receipt, TransactionValidationError> {
+        self.deposit_target = None;
+        let label = self.next_label.take();
+        let epoch = self.executor.substate_store().get_epoch();
+        let receipt = if std::mem::replace(&mut self.trace_next, false) {
+            TransactionExecutor::new(self.executor.substate_store_mut(), true)
+                .validate_and_execute(&transaction)?
+        } else {
+            self.executor.validate_and_execute(&transaction)?
+        };
+        self.last_transaction = Some(transaction.clone());
+        self.transaction_count += 1;
+        if let Some(log) = self.transaction_log.as_mut() {
+            log.transactions.push(transaction);
+        }
+        if let Some(history) = self.receipt_history.as_mut() {
+            history.receipts.push(receipt.clone());
+            history.labels.push(label);
+            history.epochs.push(epoch);
+        }
+        self.track_created(&receipt);
+        Ok(receipt)
+    }
+
+    /// Tracks the components and resources created by a successful transaction.
+    fn track_created(&mut self, receipt: &Receipt) {
+        if receipt.result.is_ok() {
+            self.components
+                .extend(receipt.new_component_addresses.iter().cloned());
+            self.resources
+                .extend(receipt.new_resource_addresses.iter().cloned());
+        }
+    }
+
+    /// Publishes a given package to the transaction execution ledger.
     ///
     /// # Arguments
     ///
-    /// * `name` - The name of the user.
+    /// * `name` - The name of the package.
+    /// * `package` - The package as a binary array.
     ///
     /// # Examples
     ///
@@ -271,21 +749,31 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
     ///
-    /// env.create_user("test user");
-    ///
-    /// env.acting_as("test user");
-    ///
-    /// assert_eq!(env.get_current_user(), *env.get_user("test user"))
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
     /// ```
-    pub fn acting_as(&mut self, name: &str) -> &mut Self {
-        let user = self.get_user(name);
-        let key = user.key;
-        let account = user.account;
-        self.current_user = Some(User { key, account });
+    pub fn publish_package(&mut self, name: &str, package: &[u8]) -> &mut Self {
+        let package_addr = self.executor.publish_package(package).unwrap();
+        self.transaction_count += 1;
+        self.packages.insert(String::from(name), package_addr);
+        self.packages_code
+            .insert(String::from(name), package.to_vec());
+
+        //If first package set as default
+        match self.current_package {
+            Some(_) => {}
+            None => self.current_package = Some(package_addr),
+        }
         self
     }
 
-    /// Returns the current test user.
+    /// Publishes several packages in order and sets the first one as the current package.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The names and binary arrays of the packages.
     ///
     /// # Examples
     ///
@@ -297,23 +785,28 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
     ///
-    /// let user = env.create_user("acc1");
-    ///
-    /// let current_user = env.get_current_user();
+    /// env.publish_packages(&[
+    ///     ("package", include_code!("../tests/assets/hello-world", "hello_world")),
+    ///     ("other", include_code!("../tests/assets/hello-world", "hello_world")),
+    /// ]);
     ///
-    /// assert_eq!(user, current_user);
+    /// assert_eq!(env.get_current_package(), env.get_package("package"));
     /// ```
-    pub fn get_current_user(&self) -> (&User, &EcdsaPrivateKey) {
-        match &self.current_user {
-            Some(user) => match self.users_pk.get(&user.account) {
-                Some(private_key) => (user, private_key),
-                None => panic!("For some reason there is no private key for {:?}.", user),
-            },
-            None => panic!("Fatal error, no user specified aborting"),
+    pub fn publish_packages(&mut self, entries: &[(&str, &[u8])]) -> &mut Self {
+        for (name, package) in entries {
+            self.publish_package(name, package);
         }
-    }
+        if let Some((name, _)) = entries.first() {
+            self.using_package(name);
+        }
+        self
+    }
 
-    /// Returns the current test package.
+    /// Retrieve a test environment package by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the package.
     ///
     /// # Examples
     ///
@@ -330,50 +823,63 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     ///     include_code!("../tests/assets/hello-world", "hello_world")
     /// );
     ///
-    /// let current_package = env.get_current_package();
+    /// let package = env.get_package("package");
     /// ```
-    pub fn get_current_package(&self) -> PackageAddress {
-        match self.current_package {
-            Some(package) => package,
-            None => panic!("Fatal error, no package specified aborting"),
+    pub fn get_package(&self, name: &str) -> PackageAddress {
+        self.try_get_package(name)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Retrieve a test environment package by name, or an error if there is none.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the package.
+    pub fn try_get_package(&self, name: &str) -> Result<PackageAddress, TestEnvError> {
+        match self.packages.get(name) {
+            Some(&package) => Ok(package),
+            None => Err(TestEnvError::UnknownPackage(String::from(name))),
         }
     }
 
-    /// Creates a token returns a ResourceManager
+    /// Sets the current package of the test environment.
+    ///
     /// # Arguments
     ///
-    /// * `max_supply` - A decimal that defines the supply
+    /// * `name` - The name of the package.
     ///
     /// # Examples
+    ///
     /// ```
     /// use scrypto_unit::*;
-    /// use radix_engine::ledger::InMemorySubstateStore;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
     ///
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
-    /// env.create_user("acc1");
-    /// let token = env.create_token(10000.into());
+    ///
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// env.using_package("package");
     /// ```
-    pub fn create_token(&mut self, max_supply: Decimal) -> ResourceAddress {
-        let (user, private_key) = self.get_current_user();
-        let transaction = TransactionBuilder::new()
-            .new_token_fixed(HashMap::new(), max_supply.into())
-            .call_method_with_all_resources(user.account, "deposit_batch")
-            .build(self.executor.get_nonce([user.key]))
-            .sign([private_key]);
-        let receipt = self.executor.validate_and_execute(&transaction).unwrap();
+    pub fn using_package(&mut self, name: &str) -> &mut Self {
+        let package = self.get_package(name);
+        self.current_package = Some(package);
 
-        return receipt.new_resource_addresses[0];
+        self
     }
 
-    /// Makes a function call and returns a Receipt
+    /// Create a test user.
+    ///
     /// # Arguments
     ///
-    /// * `package_name`  - The name of the package as named in the blueprint
-    /// * `function_name` - The name of the function to call
-    /// * `params`        - A vector of Strings with the arguments to pass into the function
+    /// * `name` - The name of the user.
     ///
     /// # Examples
+    ///
     /// ```
     /// use scrypto_unit::*;
     /// use radix_engine::ledger::*;
@@ -381,39 +887,36 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     ///
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
-    /// env.create_user("acc1");
-    /// env.publish_package(
-    ///     "package",
-    ///     include_code!("../tests/assets/hello-world", "hello_world")
-    /// );
-    /// let receipt = env.call_function("Hello", "new", vec!["1".to_owned()]);
-    /// assert!(receipt.result.is_ok());
+    ///
+    /// env.create_user("test user");
     /// ```
-    pub fn call_function(
-        &mut self,
-        blueprint_name: &str,
-        function_name: &str,
-        params: Vec<Vec<u8>>,
-    ) -> Receipt {
-        let (user, private_key) = self.get_current_user();
-        let package = self.get_current_package();
-        let transaction = TransactionBuilder::new()
-            .call_function(package, blueprint_name, function_name, params)
-            .call_method_with_all_resources(user.account, "deposit_batch")
-            .build(self.executor.get_nonce([user.key]))
-            .sign([private_key]);
-        let receipt = self.executor.validate_and_execute(&transaction).unwrap();
-        receipt
+    pub fn create_user(&mut self, name: &str) -> User {
+        // public_key, private_key, address
+        let (key, private_key, account) = self.executor.new_account();
+        self.transaction_count += 1;
+        self.users.insert(String::from(name), User { key, account });
+        self.components.push(account);
+
+        let usr = User { key, account };
+        //adding users private key to Env
+        self.users_pk.insert(account, private_key);
+
+        //If first user set as default
+        match self.current_user {
+            Some(_) => {}
+            None => self.current_user = Some(usr),
+        }
+        usr
     }
 
-    /// Makes a method call and returns a Receipt
+    /// Retrieve a test user by name.
+    ///
     /// # Arguments
     ///
-    /// * `Component`   - A reference to the ComponentAddress of the component
-    /// * `method_name` - The name of the method
-    /// * `params`      - A vector of Strings with the arguments to pass in the method
+    /// * `name` - The name of the user.
     ///
     /// # Examples
+    ///
     /// ```
     /// use scrypto_unit::*;
     /// use radix_engine::ledger::*;
@@ -422,104 +925,38 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
     ///
-    /// env.create_user("acc1");
-    /// env.publish_package(
-    ///     "package",
-    ///     include_code!("../tests/assets/hello-world", "hello_world")
-    /// );
-    ///
-    /// let receipt = env.call_function("Hello", "new", vec!["1".to_owned()]);
-    /// assert!(receipt.result.is_ok());
-    /// let component = receipt.component(0).unwrap();
+    /// env.create_user("test user");
     ///
-    /// let receipt_method = env.call_method(
-    ///     &component,
-    ///     "update_state",
-    ///     vec!["2".to_owned()]
-    /// );
-    /// assert!(receipt_method.result.is_ok());
+    /// let user = env.get_user("test user");
     /// ```
-    pub fn call_method(
-        &mut self,
-        component: ComponentAddress,
-        method_name: &str,
-        params: Vec<Vec<u8>>,
-    ) -> Receipt {
-        let (user, private_key) = self.get_current_user();
-        let transaction = TransactionBuilder::new()
-            .call_method(component, method_name, params)
-            .call_method_with_all_resources(user.account, "deposit_batch")
-            .build(self.executor.get_nonce([user.key]))
-            .sign([private_key]);
-        let receipt = self.executor.validate_and_execute(&transaction).unwrap();
-        receipt
+    pub fn get_user(&self, name: &str) -> &User {
+        self.try_get_user(name)
+            .unwrap_or_else(|error| panic!("{}", error))
     }
 
-    pub fn call_method_auth(
-        &mut self,
-        component: ComponentAddress,
-        method_name: &str,
-        admin_badge: ResourceAddress,
-        params: Vec<Vec<u8>>,
-    ) -> Receipt {
-        let (user, private_key) = self.get_current_user();
-        let transaction = TransactionBuilder::new()
-            .call_method(user.account, "create_proof", args![admin_badge])
-            .call_method(component, method_name, params)
-            .call_method_with_all_resources(user.account, "deposit_batch")
-            .build(self.executor.get_nonce([user.key]))
-            .sign([private_key]);
-        let receipt = self.executor.validate_and_execute(&transaction).unwrap();
-        receipt
+    /// Retrieve a test user by name, or an error if there is none.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the user.
+    pub fn try_get_user(&self, name: &str) -> Result<&User, TestEnvError> {
+        match self.users.get(name) {
+            Some(user) => Ok(user),
+            None => Err(TestEnvError::UnknownUser(String::from(name))),
+        }
     }
-    // TODO: dropped v0.4.1
-    // fn get_vault_info(
-    //     ledger: &L,
-    //     component_address: &ComponentAddress,
-    //     vid: &Vault,
-    // ) -> (ResourceAddress, Contents) {
-    //     let vault = ledger.get_vault(&component_address, vid).unwrap();
-
-    //     let resource_def = ledger.get_resource_def(vault.resource_address()).unwrap();
-    //     let contents = match resource_def.resource_type() {
-    //         ResourceType::Fungible { .. } => Contents::Amount(vault.amount()),
-    //         ResourceType::NonFungible => {
-    //             Contents::NonFungibleIds(vault.get_non_fungible_ids().unwrap())
-    //         }
-    //     };
-    //     let resource_def_address = vault.resource_address();
-
-    //     (resource_def_address, contents)
-    // }
 
-    // TODO: dropped v0.4.1
-    // fn get_lazymap_info(
-    //     ledger: &L,
-    //     component_address: &ComponentAddress,
-    //     id: &Mid,
-    // ) -> Vec<(ComponentAddress, Contents)> {
-    //     let lazy_map = ledger.get_lazy_map(component_address, id).unwrap();
-    //     lazy_map
-    //         .map()
-    //         .iter()
-    //         .flat_map(|(_, data)| {
-    //             let validated_data = validate_data(data).unwrap();
-    //             validated_data
-    //                 .vaults
-    //                 .iter()
-    //                 .map(|vid| TestEnv::get_vault_info(ledger, component_address, vid))
-    //                 .collect::<Vec<(ResourceAddress, Contents)>>() // ResourceAddress ??
-    //         })
-    //         .collect()
-    // }
-
-    /// Returns the amount of the resource for the component/account
+    /// Retrieve a copy of a test user by name.
+    ///
+    /// Unlike `get_user`, the returned `User` doesn't borrow the test environment,
+    /// so it can be held while calling other methods of the environment.
+    ///
     /// # Arguments
     ///
-    /// * `component_address`    - The ComponentAddress of the component that holds the resource
-    /// * `resource_address` - The ResourceAddress that holds the resource
+    /// * `name` - The name of the user.
     ///
     /// # Examples
+    ///
     /// ```
     /// use scrypto_unit::*;
     /// use radix_engine::ledger::*;
@@ -528,106 +965,3962 @@ impl<'l, L: SubstateStore> TestEnv<'l, L> {
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
     ///
-    /// let user = env.create_user("acc1");
-    /// let amount = env.get_amount_for_rd(user.account, RADIX_TOKEN);  
-    /// assert!( amount == 1000000.into() );
+    /// env.create_user("user1");
+    /// let token = env.create_token(10000.into());
+    /// env.create_user("user2");
+    ///
+    /// let user2 = env.user("user2");
+    /// env.transfer_resource(10.into(), &token, &user2);
     /// ```
-    pub fn get_amount_for_rd(
-        &mut self,
-        component_address: ComponentAddress,
-        resource_address: ResourceAddress,
-    ) -> Decimal {
-        let (user, private_key) = self.get_current_user();
-        let transaction_b = TransactionBuilder::new()
-            .call_method(component_address, "balance", args![resource_address])
-            .build(self.executor.get_nonce([user.key]))
-            .sign([private_key]);
-        let receipt_b = self.executor.validate_and_execute(&transaction_b).unwrap();
-        let balance: Decimal = scrypto_decode(&receipt_b.outputs[0].raw[..]).unwrap();
-        balance
-        // TODO: needs some safetyness
-
-        // let vaults = self.get_account_vaults(component_address);
-        // for (address, contents) in vaults {
-        //     if address == resource_def {
-        //         match contents {
-        //             Contents::Amount(amount) => return amount,
-        //             _ => panic!("Cannot get amount: resource {} is not fungible", address),
-        //         }
-        //     }
-        // }
+    pub fn user(&self, name: &str) -> User {
+        *self.get_user(name)
     }
 
-    // TODO: dropped v0.4.1
-    // pub fn get_non_fungible_ids_for_rd(
-    //     &mut self,
-    //     component_address: ComponentAddress,
-    //     resource_def: ResourceAddress,
-    // ) -> Vec<NonFungibleId> {
-    //     let vaults = self.get_account_vaults(component_address);
-    //     for (address, contents) in vaults {
-    //         if address == resource_def {
-    //             match contents {
-    //                 Contents::NonFungibleIds(keys) => return keys,
-    //                 _ => panic!("Cannot get amount: resource {} is not fungible", address),
-    //             }
-    //         }
-    //     }
-
-    //     Vec::new()
-    // }
-
-    // TODO: dropped v0.4.1
-    // pub fn get_account_vaults(
-    //     &mut self,
-    //     component_address: ComponentAddress,
-    // ) -> HashMap<ResourceAddress, Contents> {
-    //     let ledger = self.executor.substate_store();
-    //     let component = ledger.get_component(component_address).unwrap();
-
-    //     let state = component.state();
-    //     let validated_data = validate_data(state).unwrap();
-    //     validated_data
-    //         .lazy_maps
-    //         .iter()
-    //         .flat_map(|mid| TestEnv::get_lazymap_info(ledger, &component_address, &mid))
-    //         .collect()
-    // }
-
-    /// Transfers some resource between users
+    /// Registers an existing test user under another name.
+    ///
+    /// Both names refer to the same account and private key.
+    ///
     /// # Arguments
     ///
-    /// * `amount` - A decimal that defines the amount to transfer
-    /// * `resource_def` - The resource_def for the resource to transfer
-    /// * `to_user` - the user receiving the amount of resource
+    /// * `existing` - The name of the existing user.
+    /// * `alias` - The other name of the user.
     ///
     /// # Examples
+    ///
     /// ```
     /// use scrypto_unit::*;
-    /// use radix_engine::ledger::InMemorySubstateStore;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
     ///
     /// let mut ledger = InMemorySubstateStore::with_bootstrap();
     /// let mut env = TestEnv::new(&mut ledger);
-    /// env.create_user("user1");
-    /// let token = env.create_token(10000.into());
-    /// let user2 = env.create_user("user2");
-    /// env.transfer_resource(10.into(), &token, &user2);
+    ///
+    /// env.create_user("admin");
+    /// env.alias_user("admin", "treasury_owner");
+    ///
+    /// assert_eq!(env.get_user("treasury_owner"), env.get_user("admin"));
     /// ```
-    pub fn transfer_resource(
+    pub fn alias_user(&mut self, existing: &str, alias: &str) -> &mut Self {
+        let user = self.user(existing);
+        self.users.insert(String::from(alias), user);
+        self
+    }
+
+    /// Set the current user of the test environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("test user");
+    ///
+    /// env.acting_as("test user");
+    ///
+    /// assert_eq!(env.get_current_user(), *env.get_user("test user"))
+    /// ```
+    pub fn acting_as(&mut self, name: &str) -> &mut Self {
+        let user = self.get_user(name);
+        let key = user.key;
+        let account = user.account;
+        self.current_user = Some(User { key, account });
+        self
+    }
+
+    /// Returns the current test user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    ///
+    /// let current_user = env.get_current_user();
+    ///
+    /// assert_eq!(user, current_user);
+    /// ```
+    pub fn get_current_user(&self) -> (&User, &EcdsaPrivateKey) {
+        self.try_get_current_user()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Returns the current test user and its private key, or an error if there is none.
+    pub fn try_get_current_user(&self) -> Result<(&User, &EcdsaPrivateKey), TestEnvError> {
+        match &self.current_user {
+            Some(user) => match self.users_pk.get(&user.account) {
+                Some(private_key) => Ok((user, private_key)),
+                None => Err(TestEnvError::MissingPrivateKey(*user)),
+            },
+            None => Err(TestEnvError::NoCurrentUser),
+        }
+    }
+
+    /// Returns the current test package.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let current_package = env.get_current_package();
+    /// ```
+    pub fn get_current_package(&self) -> PackageAddress {
+        self.try_get_current_package()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Returns the current test package, or an error if there is none.
+    pub fn try_get_current_package(&self) -> Result<PackageAddress, TestEnvError> {
+        match self.current_package {
+            Some(package) => Ok(package),
+            None => Err(TestEnvError::NoCurrentPackage),
+        }
+    }
+
+    /// Checks that a current user and a current package are set, as needed by
+    /// `call_function`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// assert_eq!(env.ensure_ready(), Err(TestEnvError::NoCurrentUser));
+    ///
+    /// env.create_user("acc1");
+    /// assert_eq!(env.ensure_ready(), Err(TestEnvError::NoCurrentPackage));
+    ///
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// assert_eq!(env.ensure_ready(), Ok(()));
+    /// ```
+    pub fn ensure_ready(&self) -> Result<(), TestEnvError> {
+        self.try_get_current_user()?;
+        self.try_get_current_package()?;
+        Ok(())
+    }
+
+    /// Creates a token returns a ResourceManager
+    /// # Arguments
+    ///
+    /// * `max_supply` - A decimal that defines the supply
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::InMemorySubstateStore;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// env.create_user("acc1");
+    /// let token = env.create_token(10000.into());
+    /// ```
+    pub fn create_token(&mut self, max_supply: Decimal) -> ResourceAddress {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .new_token_fixed(HashMap::new(), max_supply.into())
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+
+        return receipt.new_resource_addresses[0];
+    }
+
+    /// Creates a non-divisible badge and returns its ResourceAddress
+    /// # Arguments
+    ///
+    /// * `supply` - A decimal that defines the number of badges deposited to the current user
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::InMemorySubstateStore;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// env.create_user("acc1");
+    /// let badge = env.create_badge(1.into());
+    /// ```
+    pub fn create_badge(&mut self, supply: Decimal) -> ResourceAddress {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .new_badge_fixed(HashMap::new(), supply)
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+
+        return receipt.new_resource_addresses[0];
+    }
+
+    /// Creates a resource from a configuration and returns its ResourceAddress
+    ///
+    /// The initial supply, if any, is deposited to the current user.
+    ///
+    /// # Arguments
+    ///
+    /// * `configure` - A closure configuring the resource, starting from `ResourceConfig::new()`
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::InMemorySubstateStore;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// env.create_user("acc1");
+    /// let badge = env.create_badge(1.into());
+    /// let token = env.create_resource(|config| {
+    ///     config
+    ///         .metadata("name", "My token")
+    ///         .divisibility(2)
+    ///         .mintable(rule!(require(badge)), LOCKED)
+    ///         .initial_supply(100.into())
+    /// });
+    /// ```
+    pub fn create_resource(
         &mut self,
-        amount: Decimal,
-        resource_to_send: &ResourceAddress,
-        to_user: &User,
+        configure: impl FnOnce(ResourceConfig) -> ResourceConfig,
+    ) -> ResourceAddress {
+        let config = configure(ResourceConfig::new());
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .create_resource(
+                config.resource_type,
+                config.metadata,
+                config.access_rules,
+                config.mint_params,
+            )
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+
+        return receipt.new_resource_addresses[0];
+    }
+
+    /// Makes a function call and returns a Receipt
+    /// # Arguments
+    ///
+    /// * `package_name`  - The name of the package as named in the blueprint
+    /// * `function_name` - The name of the function to call
+    /// * `params`        - A vector of Strings with the arguments to pass into the function
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// let receipt = env.call_function("Hello", "new", vec!["1".to_owned()]);
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn call_function(
+        &mut self,
+        blueprint_name: &str,
+        function_name: &str,
+        params: Vec<Vec<u8>>,
     ) -> Receipt {
-        let (user, private_key) = self.get_current_user();
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let package = self.get_current_package();
         let transaction = TransactionBuilder::new()
-            .withdraw_from_account_by_amount(amount, *resource_to_send, user.account)
-            .call_method_with_all_resources(to_user.account, "deposit_batch")
+            .call_function(package, blueprint_name, function_name, params)
+            .deposit_leftovers(deposit_target, user.account)
             .build(self.executor.get_nonce([user.key]))
-            .sign([private_key]);
-        let receipt = self.executor.validate_and_execute(&transaction).unwrap();
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+        receipt
+    }
+
+    /// Makes a method call and returns a Receipt
+    /// # Arguments
+    ///
+    /// * `Component`   - A reference to the ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of Strings with the arguments to pass in the method
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let receipt = env.call_function("Hello", "new", vec!["1".to_owned()]);
+    /// assert!(receipt.result.is_ok());
+    /// let component = receipt.component(0).unwrap();
+    ///
+    /// let receipt_method = env.call_method(
+    ///     &component,
+    ///     "update_state",
+    ///     vec!["2".to_owned()]
+    /// );
+    /// assert!(receipt_method.result.is_ok());
+    /// ```
+    pub fn call_method(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        self.try_call_method(component, method_name, params)
+            .unwrap()
+    }
+
+    /// Makes a method call and returns a Receipt, or the validation error if the
+    /// transaction is rejected before execution
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    pub fn try_call_method(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> Result<Receipt, TransactionValidationError> {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_method(component, method_name, params)
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.try_execute(transaction)
+    }
+
+    /// Makes a method call with invalid signatures, so the transaction is always
+    /// rejected at validation
+    ///
+    /// The transaction carries the signatures of a different transaction, which don't
+    /// verify against its own content.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let result = env.call_method_unsigned(user.account, "balance", args![RADIX_TOKEN]);
+    /// assert!(result.is_err());
+    /// ```
+    pub fn call_method_unsigned(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> Result<Receipt, TransactionValidationError> {
+        let (user, private_key) = self.get_current_user();
+        let nonce = self.executor.get_nonce([user.key]);
+        let mut transaction = TransactionBuilder::new()
+            .call_method(component, method_name, params)
+            .call_method_with_all_resources(user.account, "deposit_batch")
+            .build(nonce)
+            .sign([private_key]);
+        let other_transaction = TransactionBuilder::new()
+            .build(nonce + 1)
+            .sign([private_key]);
+        transaction.signatures = other_transaction.signatures;
+        self.try_execute(transaction)
+    }
+
+    pub fn call_method_auth(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        admin_badge: ResourceAddress,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        self.call_method_auth_multi(component, method_name, &[admin_badge], params)
+    }
+
+    /// Makes a method call with a proof of each badge in the auth zone and returns a Receipt
+    ///
+    /// A `create_proof` call is made on the current user's account for every badge before
+    /// the method call. If the current user doesn't hold one of the badges, the receipt fails
+    /// on the corresponding `create_proof` instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `badges`      - The ResourceAddresses of the badges to create proofs of
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    /// let admin_badge = receipt.new_resource_addresses[0];
+    /// let other_badge = env.create_badge(1.into());
+    ///
+    /// let receipt_method = env.call_method_auth_multi(
+    ///     component,
+    ///     "protected_update_state",
+    ///     &[admin_badge, other_badge],
+    ///     vec![scrypto_encode(&2u32)]
+    /// );
+    /// assert!(receipt_method.result.is_ok());
+    /// ```
+    pub fn call_method_auth_multi(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        badges: &[ResourceAddress],
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let mut builder = TransactionBuilder::new();
+        for badge in badges {
+            builder.call_method(user.account, "create_proof", args![*badge]);
+        }
+        let transaction = builder
+            .call_method(component, method_name, params)
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+        receipt
+    }
+
+    /// Returns the amount of the resource for the component/account
+    /// # Arguments
+    ///
+    /// * `component_address`    - The ComponentAddress of the component that holds the resource
+    /// * `resource_address` - The ResourceAddress that holds the resource
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let amount = env.get_amount_for_rd(user.account, RADIX_TOKEN);  
+    /// assert!( amount == 1000000.into() );
+    /// ```
+    pub fn get_amount_for_rd(
+        &mut self,
+        component_address: ComponentAddress,
+        resource_address: ResourceAddress,
+    ) -> Decimal {
+        let user = self.get_current_user().0;
+        let transaction_b = TransactionBuilder::new()
+            .call_method(component_address, "balance", args![resource_address])
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        // a read-only query, kept out of the recorded transactions and one-shot settings
+        let receipt_b = self.executor.validate_and_execute(&transaction_b).unwrap();
+        let balance: Decimal = scrypto_decode(&receipt_b.outputs[0].raw[..]).unwrap();
+        balance
+        // TODO: needs some safetyness
+
+        // let vaults = self.get_account_vaults(component_address);
+        // for (address, contents) in vaults {
+        //     if address == resource_def {
+        //         match contents {
+        //             Contents::Amount(amount) => return amount,
+        //             _ => panic!("Cannot get amount: resource {} is not fungible", address),
+        //         }
+        //     }
+        // }
+    }
+
+    // TODO: dropped v0.4.1
+    // pub fn get_non_fungible_ids_for_rd(
+    //     &mut self,
+    //     component_address: ComponentAddress,
+    //     resource_def: ResourceAddress,
+    // ) -> Vec<NonFungibleId> {
+    //     let vaults = self.get_account_vaults(component_address);
+    //     for (address, contents) in vaults {
+    //         if address == resource_def {
+    //             match contents {
+    //                 Contents::NonFungibleIds(keys) => return keys,
+    //                 _ => panic!("Cannot get amount: resource {} is not fungible", address),
+    //             }
+    //         }
+    //     }
+
+    //     Vec::new()
+    // }
+
+    // TODO: dropped v0.4.1
+    // pub fn get_account_vaults(
+    //     &mut self,
+    //     component_address: ComponentAddress,
+    // ) -> HashMap<ResourceAddress, Contents> {
+    //     let ledger = self.executor.substate_store();
+    //     let component = ledger.get_component(component_address).unwrap();
+
+    //     let state = component.state();
+    //     let validated_data = validate_data(state).unwrap();
+    //     validated_data
+    //         .lazy_maps
+    //         .iter()
+    //         .flat_map(|mid| TestEnv::get_lazymap_info(ledger, &component_address, &mid))
+    //         .collect()
+    // }
+
+    /// Transfers some resource between users
+    /// # Arguments
+    ///
+    /// * `amount` - A decimal that defines the amount to transfer
+    /// * `resource_def` - The resource_def for the resource to transfer
+    /// * `to_user` - the user receiving the amount of resource
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::InMemorySubstateStore;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// env.create_user("user1");
+    /// let token = env.create_token(10000.into());
+    /// let user2 = env.create_user("user2");
+    /// env.transfer_resource(10.into(), &token, &user2);
+    /// ```
+    pub fn transfer_resource(
+        &mut self,
+        amount: Decimal,
+        resource_to_send: &ResourceAddress,
+        to_user: &User,
+    ) -> Receipt {
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .withdraw_from_account_by_amount(amount, *resource_to_send, user.account)
+            .call_method_with_all_resources(to_user.account, "deposit_batch")
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+
+        receipt
+    }
+
+    /// Returns the package and blueprint name a component was instantiated from.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The ComponentAddress of the component
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// let (package, blueprint_name) = env.component_blueprint(component);
+    /// assert_eq!(package, env.get_package("package"));
+    /// assert_eq!(blueprint_name, "Hello");
+    /// ```
+    pub fn component_blueprint(&self, component: ComponentAddress) -> (PackageAddress, String) {
+        let component = self.get_component(component);
+        (
+            component.package_address(),
+            component.blueprint_name().to_owned(),
+        )
+    }
+
+    /// Reads a component substate from the ledger.
+    fn get_component(&self, component_address: ComponentAddress) -> radix_engine::model::Component {
+        match self
+            .executor
+            .substate_store()
+            .get_decoded_substate(&component_address)
+        {
+            Some((component, _)) => component,
+            None => panic!("No component {} found.", component_address),
+        }
+    }
+
+    /// Returns true if calling a component method is protected by an access rule
+    /// other than `allow_all`.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// assert!(env.method_requires_auth(component, "protected_update_state"));
+    /// assert!(!env.method_requires_auth(component, "update_state"));
+    /// ```
+    pub fn method_requires_auth(&self, component: ComponentAddress, method_name: &str) -> bool {
+        self.get_component(component)
+            .access_rules()
+            .iter()
+            .any(|access_rules| !matches!(access_rules.get(method_name), AccessRule::AllowAll))
+    }
+
+    /// Decodes the current state of a component.
+    ///
+    /// `T` mirrors the blueprint struct: same fields, in the same order.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The ComponentAddress of the component
+    pub fn component_state<T: Decode>(&self, component: ComponentAddress) -> T {
+        let component_state = self.get_component(component);
+        match scrypto_decode(component_state.state()) {
+            Ok(state) => state,
+            Err(error) => panic!(
+                "Failed to decode state of component {} as {}: {:?}",
+                component,
+                std::any::type_name::<T>(),
+                error
+            ),
+        }
+    }
+
+    /// Asserts that the current state of a component equals the expected value.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The ComponentAddress of the component
+    /// * `expected`  - The expected state, mirroring the blueprint struct
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// #[derive(TypeId, Decode, PartialEq, Debug)]
+    /// struct HelloState {
+    ///     state: u32,
+    ///     admin_badge: ResourceAddress,
+    /// }
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    /// let admin_badge = receipt.new_resource_addresses[0];
+    ///
+    /// env.call_method(component, "update_state", vec![scrypto_encode(&2u32)]);
+    /// env.assert_component_state_eq(component, HelloState { state: 2, admin_badge });
+    /// ```
+    pub fn assert_component_state_eq<T: Decode + PartialEq + fmt::Debug>(
+        &self,
+        component: ComponentAddress,
+        expected: T,
+    ) {
+        let actual: T = self.component_state(component);
+        assert_eq!(
+            actual, expected,
+            "State of component {} doesn't match",
+            component
+        );
+    }
+
+    /// Tops up the XRD balance of an existing user from the system faucet.
+    ///
+    /// The faucet hands out fixed amounts of XRD and can't take any back, so the XRD
+    /// left over after depositing `amount` to the user goes to a faucet sink account.
+    /// The sink is created by the first call, and isn't a registered user or listed in
+    /// `tracked_components`; `faucet_sink` returns its address, e.g. to include it in
+    /// `assert_xrd_conserved`. The transaction is signed by the current user.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`   - The name of the user to fund
+    /// * `amount` - The amount of XRD to deposit
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// env.fund_xrd("acc1", 500.into());
+    /// assert_eq!(env.get_amount_for_rd(user.account, RADIX_TOKEN), 1000500.into());
+    /// ```
+    pub fn fund_xrd(&mut self, name: &str, amount: Decimal) -> Receipt {
+        let account = self.get_user(name).account;
+        let sink = match self.faucet_sink {
+            Some(sink) => sink,
+            None => {
+                let (_, _, sink) = self.executor.new_account();
+                self.transaction_count += 1;
+                self.faucet_sink = Some(sink);
+                sink
+            }
+        };
+
+        let user = self.get_current_user().0;
+        let mut builder = TransactionBuilder::new();
+        let mut faucet_total = Decimal::zero();
+        while faucet_total < amount {
+            builder.call_method(SYSTEM_COMPONENT, "free_xrd", vec![]);
+            faucet_total = faucet_total + Decimal::from(FAUCET_AMOUNT);
+        }
+        let transaction = builder
+            .take_from_worktop_by_amount(amount, RADIX_TOKEN, |builder, bucket_id| {
+                builder.call_method(account, "deposit", args![Bucket(bucket_id)])
+            })
+            .call_method_with_all_resources(sink, "deposit_batch")
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.execute(transaction)
+    }
+
+    /// Returns the account receiving the XRD left over by `fund_xrd`, or `None` if
+    /// `fund_xrd` hasn't been called yet.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// assert_eq!(env.faucet_sink(), None);
+    ///
+    /// env.fund_xrd("acc1", 500.into());
+    /// // the sink starts with the XRD of a new account
+    /// let sink = env.faucet_sink().unwrap();
+    /// assert_eq!(env.get_amount_for_rd(sink, RADIX_TOKEN), 1999500.into());
+    /// ```
+    pub fn faucet_sink(&self) -> Option<ComponentAddress> {
+        self.faucet_sink
+    }
+
+    /// Returns a human-readable listing of the instructions of the most recently
+    /// executed transaction, one instruction per line.
+    ///
+    /// Transactions rejected by validation are not executed and don't replace it.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.create_token(10000.into());
+    /// println!("{}", env.last_manifest());
+    /// assert!(env.last_manifest().contains("deposit_batch"));
+    /// ```
+    pub fn last_manifest(&self) -> String {
+        match &self.last_transaction {
+            Some(transaction) => transaction
+                .transaction
+                .instructions
+                .iter()
+                .enumerate()
+                .map(|(index, instruction)| format!("{}: {:?}", index, instruction))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            None => String::new(),
+        }
+    }
+
+    /// Makes the next call deposit the resources left on the worktop to the given
+    /// account instead of the current user's.
+    ///
+    /// Applies to the next transaction only. Helpers that deposit what is left on the
+    /// worktop, such as `call_method` or `create_token`, use it, and the others drop it.
+    ///
+    /// # Arguments
+    ///
+    /// * `account` - The ComponentAddress of the account receiving the resources
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let treasury = env.create_user("treasury");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let receipt = env
+    ///     .with_deposit_to(treasury.account)
+    ///     .call_function("Hello", "instantiate", vec![]);
+    /// let admin_badge = receipt.new_resource_addresses[0];
+    /// assert_eq!(env.get_amount_for_rd(treasury.account, admin_badge), 1.into());
+    /// ```
+    pub fn with_deposit_to(&mut self, account: ComponentAddress) -> &mut Self {
+        self.deposit_target = Some(DepositTarget::Account(account));
+        self
+    }
+
+    /// Makes the next call leave out the deposit of the resources left on the worktop,
+    /// so it fails if the called method returns any resource.
+    ///
+    /// Applies to the next transaction only. Helpers that deposit what is left on the
+    /// worktop, such as `call_method` or `create_token`, use it, and the others drop it.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// // `instantiate` returns the admin badge, which is left on the worktop
+    /// let receipt = env.without_deposit().call_function("Hello", "instantiate", vec![]);
+    /// assert!(receipt.result.is_err());
+    /// ```
+    pub fn without_deposit(&mut self) -> &mut Self {
+        self.deposit_target = Some(DepositTarget::Omit);
+        self
+    }
+
+    /// Returns the deposit target of the current call and resets it for the next one.
+    fn take_deposit_target(&mut self) -> DepositTarget {
+        self.deposit_target
+            .take()
+            .unwrap_or(DepositTarget::CurrentUser)
+    }
+
+    /// Reads and decodes the data of a non-fungible.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The ResourceAddress of the non-fungible resource
+    /// * `id`       - The id of the non-fungible
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// #[derive(NonFungibleData, Debug, PartialEq)]
+    /// struct Character {
+    ///     name: String,
+    ///     #[scrypto(mutable)]
+    ///     level: u8,
+    /// }
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let id = NonFungibleId::from_u32(1);
+    /// let characters = env.create_resource(|config| {
+    ///     config.non_fungible().initial_non_fungible_supply(vec![(
+    ///         id.clone(),
+    ///         Character { name: "Hero".to_owned(), level: 1 },
+    ///     )])
+    /// });
+    ///
+    /// let character: Character = env.non_fungible_data(characters, &id).unwrap();
+    /// assert_eq!(character.level, 1);
+    /// ```
+    pub fn non_fungible_data<T: NonFungibleData>(
+        &self,
+        resource: ResourceAddress,
+        id: &NonFungibleId,
+    ) -> Result<T, TestEnvError> {
+        let non_fungible: NonFungible = match self
+            .executor
+            .substate_store()
+            .get_decoded_child_substate(&resource, id)
+        {
+            Some((non_fungible, _)) => non_fungible,
+            None => return Err(TestEnvError::UnknownNonFungible(resource, id.clone())),
+        };
+        match T::decode(non_fungible.immutable_data(), non_fungible.mutable_data()) {
+            Ok(data) => Ok(data),
+            Err(error) => panic!(
+                "Failed to decode non-fungible {} as {}: {:?}",
+                id,
+                std::any::type_name::<T>(),
+                error
+            ),
+        }
+    }
+
+    /// Creates a test user holding the given resources, transferred from the current user.
+    ///
+    /// Nothing is created if the current user doesn't hold enough of every resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`     - The name of the user.
+    /// * `holdings` - The resources and amounts to transfer to the new user.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("admin");
+    /// let token_a = env.create_token(1000.into());
+    /// let token_b = env.create_token(1000.into());
+    ///
+    /// let trader = env
+    ///     .create_user_holding("trader", &[(token_a, 100.into()), (token_b, 50.into())])
+    ///     .unwrap();
+    /// assert_eq!(env.get_amount_for_rd(trader.account, token_a), 100.into());
+    /// ```
+    pub fn create_user_holding(
+        &mut self,
+        name: &str,
+        holdings: &[(ResourceAddress, Decimal)],
+    ) -> Result<User, TestEnvError> {
+        let account = self.try_get_current_user()?.0.account;
+
+        let mut required: HashMap<ResourceAddress, Decimal> = HashMap::new();
+        for (resource, amount) in holdings {
+            let total = required.entry(*resource).or_insert_with(Decimal::zero);
+            *total = *total + *amount;
+        }
+        for (resource, required) in required {
+            let available = self.get_amount_for_rd(account, resource);
+            if available < required {
+                return Err(TestEnvError::InsufficientBalance {
+                    resource,
+                    required,
+                    available,
+                });
+            }
+        }
+
+        let user = self.create_user(name);
+        for (resource, amount) in holdings {
+            self.transfer_resource(*amount, resource, &user);
+        }
+        Ok(user)
+    }
+
+    /// Executes the next transaction with tracing, whatever the tracing setting of the
+    /// test environment.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let receipt = env
+    ///     .trace_next_call()
+    ///     .call_method(user.account, "balance", args![RADIX_TOKEN]);
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn trace_next_call(&mut self) -> &mut Self {
+        self.trace_next = true;
+        self
+    }
+
+    /// Returns the components created through this test environment, in creation order.
+    ///
+    /// This includes the user accounts, but not the system components created when the
+    /// ledger was bootstrapped, nor the account receiving the leftovers of `fund_xrd`.
+    /// The components are tracked from the receipts of the test environment, as the
+    /// v0.4.1 substate store can't be enumerated, so those created by another test
+    /// environment sharing the ledger, e.g. one whose users were imported with
+    /// `import_registry`, aren't listed.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    ///
+    /// assert_eq!(
+    ///     env.tracked_components(),
+    ///     vec![user.account, receipt.new_component_addresses[0]]
+    /// );
+    /// ```
+    pub fn tracked_components(&self) -> Vec<ComponentAddress> {
+        self.components.clone()
+    }
+
+    /// Returns XRD followed by every resource created through the test environment, in
+    /// creation order.
+    ///
+    /// Resources created by a blueprint are included, as they are listed in the receipt
+    /// of the transaction that created them.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let token = env.create_token(10000.into());
+    ///
+    /// assert_eq!(env.all_resources(), vec![RADIX_TOKEN, token]);
+    /// ```
+    pub fn all_resources(&self) -> Vec<ResourceAddress> {
+        self.resources.clone()
+    }
+
+    /// Makes a method call, panics if it failed and returns the decoded output of the method
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// let old_state: u32 = env.call_method_ok(component, "update_state", params!(2u32));
+    /// assert_eq!(old_state, 0);
+    /// ```
+    pub fn call_method_ok<T: Decode>(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> T {
+        let mut receipt = self.call_method(component, method_name, params);
+        if let Err(error) = &receipt.result {
+            panic!("Call to method {:?} failed: {:?}", method_name, error);
+        }
+        return_of_call_method(&mut receipt, method_name)
+    }
+
+    /// Transfers the current user's entire balance of a resource to another user
+    ///
+    /// If the current user holds none of the resource, an empty transaction is executed
+    /// instead, so the returned receipt is successful.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The ResourceAddress of the resource to transfer
+    /// * `to_user`  - The user receiving the resource
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// let user1 = env.create_user("user1");
+    /// let token = env.create_token(10000.into());
+    /// let user2 = env.create_user("user2");
+    ///
+    /// env.transfer_all(&token, &user2);
+    /// assert_eq!(env.get_amount_for_rd(user1.account, token), 0.into());
+    /// assert_eq!(env.get_amount_for_rd(user2.account, token), 10000.into());
+    /// ```
+    pub fn transfer_all(&mut self, resource: &ResourceAddress, to_user: &User) -> Receipt {
+        let account = self.get_current_user().0.account;
+        let amount = self.get_amount_for_rd(account, *resource);
+        if amount > Decimal::zero() {
+            return self.transfer_resource(amount, resource, to_user);
+        }
+
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.execute(transaction)
+    }
+
+    /// Makes the same method call several times and returns the Receipts
+    ///
+    /// Each call is a separate transaction with its own nonce.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    /// * `times`       - The number of calls
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let receipts = env.call_method_repeat(user.account, "balance", args![RADIX_TOKEN], 3);
+    /// assert!(receipts.iter().all(|receipt| receipt.result.is_ok()));
+    /// ```
+    pub fn call_method_repeat(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+        times: usize,
+    ) -> Vec<Receipt> {
+        (0..times)
+            .map(|_| self.call_method(component, method_name, params.clone()))
+            .collect()
+    }
+
+    /// Sets the user paying for the transactions of the call helpers.
+    ///
+    /// `call_function`, `call_method` and `call_method_auth` are then signed by the fee
+    /// payer first and the current user second, while resources are still deposited to
+    /// the current user. Radix Engine v0.4.1 doesn't charge fees, so the fee payer only
+    /// adds its signature for now.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the paying user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("relayer");
+    /// let user = env.create_user("user");
+    /// env.acting_as("user").set_fee_payer("relayer");
+    ///
+    /// let receipt = env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn set_fee_payer(&mut self, name: &str) -> &mut Self {
+        self.fee_payer = Some(self.user(name));
+        self
+    }
+
+    /// Stops using a fee payer, so the call helpers are signed by the current user only.
+    pub fn clear_fee_payer(&mut self) -> &mut Self {
+        self.fee_payer = None;
+        self
+    }
+
+    /// Returns the private keys signing the transactions of the call helpers.
+    fn signing_keys(&self) -> Vec<&EcdsaPrivateKey> {
+        let user = self.get_current_user().0;
+        let mut keys = Vec::new();
+        if let Some(fee_payer) = &self.fee_payer {
+            if fee_payer.account != user.account {
+                keys.extend(self.account_keys(fee_payer));
+            }
+        }
+        keys.extend(self.account_keys(user));
+        keys
+    }
+
+    /// Compiles and publishes a built-in package the first time it is needed and returns
+    /// its address.
+    #[cfg(feature = "builtin-blueprints")]
+    fn builtin_package(
+        &mut self,
+        name: &'static str,
+        compile: impl FnOnce() -> Vec<u8>,
+    ) -> PackageAddress {
+        if let Some(package_address) = self.builtin_packages.get(name) {
+            return *package_address;
+        }
+        let package_address = self.executor.publish_package(&compile()).unwrap();
+        self.transaction_count += 1;
+        self.builtin_packages.insert(name, package_address);
+        package_address
+    }
+
+    /// Returns the private keys of the account of a user, its own key first and then
+    /// the keys added with `add_key_to_user`.
+    fn account_keys(&self, user: &User) -> Vec<&EcdsaPrivateKey> {
+        let mut keys = match self.users_pk.get(&user.account) {
+            Some(private_key) => vec![private_key],
+            None => panic!("{}", TestEnvError::MissingPrivateKey(*user)),
+        };
+        if let Some(extra_keys) = self.extra_keys.get(&user.account) {
+            keys.extend(extra_keys);
+        }
+        keys
+    }
+
+    /// Copies the named users and packages of another test environment into this one,
+    /// so the same names resolve in both.
+    ///
+    /// Only the bookkeeping is copied: `users`, `users_pk` and `packages`, plus the
+    /// package code and the keys added with `add_key_to_user`. The ledger state is not,
+    /// so the accounts and packages only exist here if both environments were built the
+    /// same way, e.g. by replaying a transaction log.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The test environment to copy the registries from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// env.create_user("acc1");
+    ///
+    /// let mut other_ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut other_env = TestEnv::new(&mut other_ledger);
+    /// other_env.import_registry(&env);
+    ///
+    /// assert_eq!(other_env.get_user("acc1"), env.get_user("acc1"));
+    /// ```
+    pub fn import_registry<M: SubstateStore>(&mut self, other: &TestEnv<'_, M>) -> &mut Self {
+        for (name, user) in &other.users {
+            self.users.insert(name.clone(), *user);
+        }
+        for (account, private_key) in &other.users_pk {
+            let private_key = EcdsaPrivateKey::from_bytes(&private_key.to_bytes()).unwrap();
+            self.users_pk.insert(*account, private_key);
+        }
+        for (account, extra_keys) in &other.extra_keys {
+            let extra_keys = extra_keys
+                .iter()
+                .map(|private_key| EcdsaPrivateKey::from_bytes(&private_key.to_bytes()).unwrap())
+                .collect();
+            self.extra_keys.insert(*account, extra_keys);
+        }
+        for (name, package) in &other.packages {
+            self.packages.insert(name.clone(), *package);
+        }
+        for (name, code) in &other.packages_code {
+            self.packages_code.insert(name.clone(), code.clone());
+        }
+        if self.current_user.is_none() {
+            self.current_user = other.current_user;
+        }
+        if self.current_package.is_none() {
+            self.current_package = other.current_package;
+        }
+        self
+    }
+
+    /// Asserts that two accounts hold the same amount of a resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `a`        - The first account.
+    /// * `b`        - The second account.
+    /// * `resource` - The resource to compare.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let alice = env.create_user("alice");
+    /// let bob = env.create_user("bob");
+    /// env.assert_balances_equal(alice.account, bob.account, RADIX_TOKEN);
+    /// ```
+    pub fn assert_balances_equal(
+        &mut self,
+        a: ComponentAddress,
+        b: ComponentAddress,
+        resource: ResourceAddress,
+    ) {
+        let amount_a = self.get_amount_for_rd(a, resource);
+        let amount_b = self.get_amount_for_rd(b, resource);
+        assert!(
+            amount_a == amount_b,
+            "Balances of {} differ: {} holds {} but {} holds {}",
+            resource,
+            a,
+            amount_a,
+            b,
+            amount_b
+        );
+    }
+
+    /// Reads a compiled package from disk and publishes it to the transaction execution
+    /// ledger.
+    ///
+    /// Packages built with scrypto v0.4.1 embed their ABI in the WASM, so the `.wasm`
+    /// file is all that is needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`      - The name of the package.
+    /// * `wasm_path` - The path of the compiled `.wasm` file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.publish_package_from_file(
+    ///     "package",
+    ///     "target/wasm32-unknown-unknown/release/hello_world.wasm",
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn publish_package_from_file(
+        &mut self,
+        name: &str,
+        wasm_path: &str,
+    ) -> std::io::Result<&mut Self> {
+        let package = std::fs::read(wasm_path).map_err(|error| {
+            std::io::Error::new(
+                error.kind(),
+                format!("Cannot read package {}: {}", wasm_path, error),
+            )
+        })?;
+        Ok(self.publish_package(name, &package))
+    }
+
+    /// Makes a method call with arguments given as `ScryptoValue`s, for arguments built
+    /// at runtime whose types aren't known at compile time.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `args`        - The arguments to pass in the method
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    /// use scrypto::values::ScryptoValue;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let receipt = env.call_method_values(
+    ///     user.account,
+    ///     "balance",
+    ///     vec![ScryptoValue::from_value(&RADIX_TOKEN)],
+    /// );
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn call_method_values(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        args: Vec<ScryptoValue>,
+    ) -> Receipt {
+        let params = args.into_iter().map(|value| value.raw).collect();
+        self.call_method(component, method_name, params)
+    }
+
+    /// Asserts that the XRD held by the given accounts adds up to the expected total.
+    ///
+    /// Radix Engine v0.4.1 doesn't charge transaction fees, so no XRD is consumed and
+    /// `expected_total` is the exact sum expected across the accounts.
+    ///
+    /// # Arguments
+    ///
+    /// * `accounts`       - The accounts holding the XRD.
+    /// * `expected_total` - The expected sum of their XRD balances.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let alice = env.create_user("alice");
+    /// let bob = env.create_user("bob");
+    /// env.transfer_resource(100.into(), &RADIX_TOKEN, &bob);
+    ///
+    /// env.assert_xrd_conserved(&[alice.account, bob.account], 2000000.into());
+    /// ```
+    pub fn assert_xrd_conserved(&mut self, accounts: &[ComponentAddress], expected_total: Decimal) {
+        let mut total = Decimal::zero();
+        for account in accounts {
+            total = total + self.get_amount_for_rd(*account, RADIX_TOKEN);
+        }
+        assert!(
+            total == expected_total,
+            "XRD not conserved: the accounts hold {} XRD in total, expected {}",
+            total,
+            expected_total
+        );
+    }
+
+    /// Returns the ABI of a blueprint, with the signatures of its functions and methods.
+    ///
+    /// The blueprints of a package can't be listed in v0.4.1, so the blueprint name must
+    /// be known.
+    ///
+    /// # Arguments
+    ///
+    /// * `package`        - The package of the blueprint.
+    /// * `blueprint_name` - The name of the blueprint.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let package = env.get_package("package");
+    /// let abi = env.blueprint_abi(package, "Hello");
+    /// assert!(abi.functions.iter().any(|function| function.name == "instantiate"));
+    /// ```
+    pub fn blueprint_abi(
+        &mut self,
+        package: PackageAddress,
+        blueprint_name: &str,
+    ) -> scrypto::abi::Blueprint {
+        self.executor
+            .export_abi(package, blueprint_name)
+            .unwrap_or_else(|error| {
+                panic!(
+                    "Cannot export the ABI of blueprint {} in package {}: {:?}",
+                    blueprint_name, package, error
+                )
+            })
+    }
+
+    /// Makes a method call whose receipt is recorded in `receipt_history` with a label,
+    /// to tell the steps of a scenario apart when inspecting the history.
+    ///
+    /// # Arguments
+    ///
+    /// * `label`       - The label of the transaction
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// env.record_receipts();
+    /// env.call_method_labeled("setup: check balance", user.account, "balance", args![RADIX_TOKEN]);
+    ///
+    /// let history = env.receipt_history.as_ref().unwrap();
+    /// assert_eq!(history.labels[0].as_deref(), Some("setup: check balance"));
+    /// ```
+    pub fn call_method_labeled(
+        &mut self,
+        label: &str,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        self.next_label = Some(String::from(label));
+        let receipt = self.call_method(component, method_name, params);
+        self.next_label = None;
+        receipt
+    }
+
+    /// Makes a method call followed by an assertion call in the same transaction, so the
+    /// assertion observes the state right after the method, before any later instruction.
+    ///
+    /// The assertion call is a method of a component that panics when its check fails,
+    /// which rolls back the whole transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    /// * `assert_call` - The component, method name and encoded arguments of the assertion
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// let receipt = env.call_with_assertion(
+    ///     component,
+    ///     "update_state",
+    ///     args![3u32],
+    ///     (component, "assert_state", args![3u32]),
+    /// );
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn call_with_assertion(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+        assert_call: (ComponentAddress, &str, Vec<Vec<u8>>),
+    ) -> Receipt {
+        let (assert_component, assert_method, assert_params) = assert_call;
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_method(component, method_name, params)
+            .call_method(assert_component, assert_method, assert_params)
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.execute(transaction)
+    }
+
+    /// Makes a method call with the given nonce instead of the one of the ledger, and
+    /// returns the validation error if the transaction is rejected
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    /// * `nonce`       - The nonce of the transaction
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let result = env.call_method_with_nonce(user.account, "balance", args![RADIX_TOKEN], 7);
+    /// assert!(result.unwrap().result.is_ok());
+    /// ```
+    pub fn call_method_with_nonce(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+        nonce: u64,
+    ) -> Result<Receipt, TransactionValidationError> {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_method(component, method_name, params)
+            .deposit_leftovers(deposit_target, user.account)
+            .build(nonce)
+            .sign(self.signing_keys());
+        self.try_execute(transaction)
+    }
+
+    /// Makes a method call and asserts that it succeeded and changed a field of the
+    /// component state by the expected delta
+    ///
+    /// The delta is computed as the value after the call minus the value before, so a
+    /// field that decreases needs a signed type, e.g. `Decimal`.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`      - The ComponentAddress of the component
+    /// * `field`          - Reads the field from the decoded component state
+    /// * `method_name`    - The name of the method
+    /// * `params`         - A vector of encoded arguments to pass in the method
+    /// * `expected_delta` - The expected change of the field
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// #[derive(TypeId, Decode)]
+    /// struct HelloState {
+    ///     state: u32,
+    ///     admin_badge: ResourceAddress,
+    /// }
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// env.assert_state_delta(
+    ///     component,
+    ///     |state: &HelloState| state.state,
+    ///     "update_state",
+    ///     args![5u32],
+    ///     5,
+    /// );
+    /// ```
+    pub fn assert_state_delta<S, T, F>(
+        &mut self,
+        component: ComponentAddress,
+        field: F,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+        expected_delta: T,
+    ) -> Receipt
+    where
+        S: Decode,
+        T: std::ops::Sub<Output = T> + PartialEq + fmt::Debug,
+        F: Fn(&S) -> T,
+    {
+        let before = field(&self.component_state::<S>(component));
+        let receipt = self.call_method(component, method_name, params);
+        assert!(
+            receipt.result.is_ok(),
+            "Call to {} failed: {:?}",
+            method_name,
+            receipt.result
+        );
+        let after = field(&self.component_state::<S>(component));
+        assert_eq!(
+            after - before,
+            expected_delta,
+            "Unexpected change of the state of component {} by {}",
+            component,
+            method_name
+        );
+        receipt
+    }
+
+    /// Compiles a transaction manifest, signs it with the current user and executes it.
+    ///
+    /// The manifest is executed as is, so it must deposit the resources it leaves on
+    /// the worktop itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - The transaction manifest, in the Radix Transaction Manifest format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let manifest = format!(
+    ///     "CALL_METHOD ComponentAddress(\"{}\") \"balance\" ResourceAddress(\"{}\");",
+    ///     user.account, RADIX_TOKEN
+    /// );
+    /// let receipt = env.execute_manifest(&manifest);
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn execute_manifest(&mut self, manifest: &str) -> Receipt {
+        let mut transaction = transaction_manifest::compile(manifest)
+            .unwrap_or_else(|error| panic!("Cannot compile the manifest: {:?}", error));
+        let user = self.get_current_user().0;
+        let nonce = self.executor.get_nonce([user.key]);
+        transaction.instructions.push(Instruction::Nonce { nonce });
+        let transaction = transaction.sign(self.signing_keys());
+        self.execute(transaction)
+    }
+
+    /// Calls a method returning a bucket and passes that bucket back to another method in
+    /// the same transaction, e.g. to borrow and repay a flash loan.
+    ///
+    /// Everything of `resource` on the worktop after the first call goes to the second
+    /// call, as its first argument followed by `repay_params`.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`     - The ComponentAddress of the component
+    /// * `borrow_method` - The name of the method returning the bucket
+    /// * `borrow_params` - A vector of encoded arguments to pass in the first method
+    /// * `repay_method`  - The name of the method taking the bucket back
+    /// * `repay_params`  - A vector of encoded arguments to pass after the bucket
+    /// * `resource`      - The resource of the returned bucket
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let receipt = env.call_method_with_returned_bucket(
+    ///     user.account,
+    ///     "withdraw_by_amount",
+    ///     args![Decimal::from(10), RADIX_TOKEN],
+    ///     "deposit",
+    ///     vec![],
+    ///     RADIX_TOKEN,
+    /// );
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn call_method_with_returned_bucket(
+        &mut self,
+        component: ComponentAddress,
+        borrow_method: &str,
+        borrow_params: Vec<Vec<u8>>,
+        repay_method: &str,
+        repay_params: Vec<Vec<u8>>,
+        resource: ResourceAddress,
+    ) -> Receipt {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_method(component, borrow_method, borrow_params)
+            .take_from_worktop(resource, |builder, bucket_id| {
+                let mut params = vec![scrypto_encode(&Bucket(bucket_id))];
+                params.extend(repay_params);
+                builder.call_method(component, repay_method, params)
+            })
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.execute(transaction)
+    }
+
+    /// Returns whether a user can withdraw an amount of a resource from their account.
+    ///
+    /// The withdrawal is attempted in a transaction signed by the user, which deposits
+    /// the resources back, so the balances are unchanged either way. A rejected or
+    /// failed transaction returns `false` instead of panicking.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`     - The name of the user.
+    /// * `resource` - The resource to withdraw.
+    /// * `amount`   - The amount to withdraw.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// assert!(env.can_withdraw("acc1", RADIX_TOKEN, 100.into()));
+    /// assert!(!env.can_withdraw("acc1", RADIX_TOKEN, 2000000.into()));
+    /// ```
+    pub fn can_withdraw(&mut self, name: &str, resource: ResourceAddress, amount: Decimal) -> bool {
+        let user = self.user(name);
+        let transaction = TransactionBuilder::new()
+            .withdraw_from_account_by_amount(amount, resource, user.account)
+            .call_method_with_all_resources(user.account, "deposit_batch")
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.account_keys(&user));
+        match self.try_execute(transaction) {
+            Ok(receipt) => receipt.result.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Publishes and instantiates a treasury component funded with XRD from the system
+    /// faucet, as a programmable source of funds.
+    ///
+    /// The treasury package is compiled and published the first time a treasury is set
+    /// up in the test environment. Only available with the `builtin-blueprints` feature.
+    ///
+    /// The treasury has the following methods, all public:
+    ///
+    /// * `deposit(bucket)` and `deposit_batch(buckets)` - Adds funds, so it can be passed
+    ///   to `with_deposit_to`.
+    /// * `dispense(resource, amount) -> Bucket` - Takes funds out.
+    /// * `balance(resource) -> Decimal` - Returns the amount held of a resource.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let treasury = env.setup_treasury();
+    ///
+    /// let receipt = env.call_method(treasury, "dispense", args![RADIX_TOKEN, Decimal::from(500)]);
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    #[cfg(feature = "builtin-blueprints")]
+    pub fn setup_treasury(&mut self) -> ComponentAddress {
+        let package_address = self.builtin_package("treasury", || {
+            compile_package!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/treasury/"))
+        });
+
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_function(package_address, "Treasury", "instantiate", vec![])
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+        let treasury = receipt.new_component_addresses[0];
+
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_method(SYSTEM_COMPONENT, "free_xrd", vec![])
+            .call_method_with_all_resources(treasury, "deposit_batch")
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.execute(transaction);
+        treasury
+    }
+
+    /// Asserts that an account holds none of a resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `account`  - The account to check.
+    /// * `resource` - The resource expected to be gone.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let token = env.create_token(10000.into());
+    /// let other = env.create_user("acc2");
+    /// env.transfer_all(&token, &other);
+    ///
+    /// env.assert_empty(user.account, token);
+    /// ```
+    pub fn assert_empty(&mut self, account: ComponentAddress, resource: ResourceAddress) {
+        let amount = self.get_amount_for_rd(account, resource);
+        assert!(
+            amount == Decimal::zero(),
+            "Expected {} to hold no {}, but it holds {}",
+            account,
+            resource,
+            amount
+        );
+    }
+
+    /// Asserts that every registered user holds the same amount of XRD.
+    ///
+    /// Users are compared in name order against the first one, and the first user whose
+    /// balance differs is reported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("alice");
+    /// env.create_user("bob");
+    /// env.assert_all_users_equal_xrd();
+    /// ```
+    pub fn assert_all_users_equal_xrd(&mut self) {
+        let users: Vec<(String, ComponentAddress)> = self
+            .users
+            .iter()
+            .map(|(name, user)| (name.clone(), user.account))
+            .collect();
+        let mut expected: Option<(String, Decimal)> = None;
+        for (name, account) in users {
+            let amount = self.get_amount_for_rd(account, RADIX_TOKEN);
+            match &expected {
+                None => expected = Some((name, amount)),
+                Some((first, first_amount)) => assert!(
+                    amount == *first_amount,
+                    "User {} holds {} XRD but {} holds {} XRD",
+                    name,
+                    amount,
+                    first,
+                    first_amount
+                ),
+            }
+        }
+    }
+
+    /// Removes a user from the test environment registry.
+    ///
+    /// The private key of the account is dropped too, unless the account is still
+    /// registered under another name (see `alias_user`). If the account was the current
+    /// user or the fee payer and no other name is left for it, the current user or the
+    /// fee payer is reset to `None`. The account itself stays in the ledger.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.remove_user("acc1");
+    ///
+    /// assert!(env.try_get_user("acc1").is_err());
+    /// assert!(env.current_user.is_none());
+    /// ```
+    pub fn remove_user(&mut self, name: &str) -> &mut Self {
+        let account = self.user(name).account;
+        self.users.remove(name);
+        if self.users.values().any(|user| user.account == account) {
+            return self;
+        }
+        self.users_pk.remove(&account);
+        self.extra_keys.remove(&account);
+        if self.current_user.map(|user| user.account) == Some(account) {
+            self.current_user = None;
+        }
+        if self.fee_payer.map(|user| user.account) == Some(account) {
+            self.fee_payer = None;
+        }
+        self
+    }
+
+    /// Removes a package from the test environment registry.
+    ///
+    /// If the package was the current package and no other name is left for it, the
+    /// current package is reset to `None`. The package itself stays in the ledger.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the package.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// env.remove_package("package");
+    ///
+    /// assert!(env.try_get_package("package").is_err());
+    /// assert!(env.current_package.is_none());
+    /// ```
+    pub fn remove_package(&mut self, name: &str) -> &mut Self {
+        let package = self.get_package(name);
+        self.packages.remove(name);
+        self.packages_code.remove(name);
+        if self.current_package == Some(package)
+            && !self.packages.values().any(|&other| other == package)
+        {
+            self.current_package = None;
+        }
+        self
+    }
+
+    /// Makes a method call passing a bucket withdrawn from the current user's account as
+    /// the first argument, followed by `params`
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `resource`    - The resource of the bucket
+    /// * `amount`      - The amount of the bucket
+    /// * `params`      - A vector of encoded arguments to pass after the bucket
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let other = env.create_user("acc2");
+    /// let receipt = env.call_method_with_bucket(
+    ///     other.account,
+    ///     "deposit",
+    ///     RADIX_TOKEN,
+    ///     100.into(),
+    ///     vec![],
+    /// );
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn call_method_with_bucket(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        resource: ResourceAddress,
+        amount: Decimal,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .withdraw_from_account_by_amount(amount, resource, user.account)
+            .take_from_worktop_by_amount(amount, resource, |builder, bucket_id| {
+                let mut bucket_params = vec![scrypto_encode(&Bucket(bucket_id))];
+                bucket_params.extend(params);
+                builder.call_method(component, method_name, bucket_params)
+            })
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.execute(transaction)
+    }
+
+    /// Makes a method call passing a proof of an amount of a resource held by the
+    /// current user as the first argument, followed by `params`
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `resource`    - The resource to prove
+    /// * `amount`      - The amount to prove
+    /// * `params`      - A vector of encoded arguments to pass after the proof
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// let receipt = env.call_method_with_proof(
+    ///     component,
+    ///     "proof_amount",
+    ///     RADIX_TOKEN,
+    ///     10.into(),
+    ///     vec![],
+    /// );
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn call_method_with_proof(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        resource: ResourceAddress,
+        amount: Decimal,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        let proof_call = ("create_proof_by_amount", args![amount, resource]);
+        self.call_method_with_account_proof(component, method_name, proof_call, params)
+    }
+
+    /// Makes a method call passing a proof of some non-fungibles held by the current user
+    /// as the first argument, followed by `params`
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `resource`    - The non-fungible resource to prove
+    /// * `ids`         - The ids of the non-fungibles to prove
+    /// * `params`      - A vector of encoded arguments to pass after the proof
+    pub fn call_method_with_proof_by_ids(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        resource: ResourceAddress,
+        ids: &BTreeSet<NonFungibleId>,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        let proof_call = ("create_proof_by_ids", args![ids.clone(), resource]);
+        self.call_method_with_account_proof(component, method_name, proof_call, params)
+    }
+
+    /// Makes a method call passing the proof created by a method of the current user's
+    /// account as the first argument.
+    fn call_method_with_account_proof(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        proof_call: (&str, Vec<Vec<u8>>),
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_method(user.account, proof_call.0, proof_call.1)
+            .pop_from_auth_zone(|builder, proof_id| {
+                let mut proof_params = vec![scrypto_encode(&Proof(proof_id))];
+                proof_params.extend(params);
+                builder.call_method(component, method_name, proof_params)
+            })
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.execute(transaction)
+    }
+
+    /// Makes a method call and returns its Receipt with the wall-clock time the
+    /// execution took
+    ///
+    /// Only the validation and execution of the transaction are timed, not building
+    /// and signing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let (receipt, duration) = env.timed_call_method(user.account, "balance", args![RADIX_TOKEN]);
+    /// assert!(receipt.result.is_ok());
+    /// println!("balance took {:?}", duration);
+    /// ```
+    pub fn timed_call_method(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> (Receipt, std::time::Duration) {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_method(component, method_name, params)
+            .deposit_leftovers(deposit_target, user.account)
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let start = std::time::Instant::now();
+        let receipt = self.execute(transaction);
+        (receipt, start.elapsed())
+    }
+
+    /// Asserts that a resource can't be split into fractional amounts.
+    ///
+    /// The current user, who must hold at least one unit of the resource, attempts to
+    /// withdraw half a unit from their account. The assertion fails if the withdrawal
+    /// succeeds, or if it fails for any other reason than the divisibility of the resource,
+    /// e.g. a withdraw rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The resource expected to be indivisible.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let badge = env.create_badge(1.into());
+    /// env.assert_indivisible(badge);
+    /// ```
+    pub fn assert_indivisible(&mut self, resource: ResourceAddress) {
+        let account = self.get_current_user().0.account;
+        let balance = self.get_amount_for_rd(account, resource);
+        if balance < Decimal::one() {
+            panic!(
+                "Cannot check the divisibility of {}: the current user holds {} of it",
+                resource, balance
+            );
+        }
+
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .withdraw_from_account_by_amount(dec!("0.5"), resource, user.account)
+            .call_method_with_all_resources(user.account, "deposit_batch")
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+        match &receipt.result {
+            Err(RuntimeError::VaultError(VaultError::ResourceContainerError(
+                ResourceContainerError::InvalidAmount(..),
+            ))) => {}
+            Err(error) => panic!(
+                "Cannot check the divisibility of {}: the withdrawal failed with {:?}",
+                resource, error
+            ),
+            Ok(()) => panic!(
+                "Resource {} is divisible: half a unit was withdrawn",
+                resource
+            ),
+        }
+    }
+
+    /// Registers an additional private key for a user, e.g. for an account whose withdraw
+    /// rule requires several signatures.
+    ///
+    /// Every transaction a helper builds for the user's account, such as `call_method`,
+    /// `transfer_resource` or `can_withdraw`, is then signed with the extra keys after
+    /// the main one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`        - The name of the user.
+    /// * `private_key` - The additional private key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let (_, private_key) = env.executor.new_key_pair();
+    /// env.add_key_to_user("acc1", private_key);
+    ///
+    /// let receipt = env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+    /// assert_eq!(receipt.validated_transaction.signers.len(), 2);
+    /// ```
+    pub fn add_key_to_user(&mut self, name: &str, private_key: EcdsaPrivateKey) -> &mut Self {
+        let account = self.user(name).account;
+        self.extra_keys
+            .entry(account)
+            .or_insert_with(Vec::new)
+            .push(private_key);
+        self
+    }
+
+    /// Makes a method call, asserts that it left exactly the expected resources on the
+    /// worktop and returns the Receipt. The resources are deposited to the deposit
+    /// target, the current user's account unless `with_deposit_to` was called before.
+    /// Panics if `without_deposit` was called before, since the taken resources must be
+    /// deposited somewhere.
+    ///
+    /// Each expected amount is taken from the worktop and deposited on its own, without a
+    /// final `deposit_batch`. The transaction fails if a resource is missing or short, or
+    /// if anything is left over, including more of an expected resource, and the assertion
+    /// lists the expected resources along with the error.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    /// * `expected`    - The resources and amounts expected on the worktop
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// env.call_method_expecting_worktop(
+    ///     user.account,
+    ///     "withdraw_by_amount",
+    ///     args![Decimal::from(10), RADIX_TOKEN],
+    ///     vec![(RADIX_TOKEN, 10.into())],
+    /// );
+    /// ```
+    pub fn call_method_expecting_worktop(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+        expected: Vec<(ResourceAddress, Decimal)>,
+    ) -> Receipt {
+        let user = self.get_current_user().0;
+        let account = match self.take_deposit_target() {
+            DepositTarget::CurrentUser => user.account,
+            DepositTarget::Account(account) => account,
+            DepositTarget::Omit => panic!(
+                "Cannot take the expected resources of {} of component {} without a deposit",
+                method_name, component
+            ),
+        };
+        let mut builder = TransactionBuilder::new();
+        builder.call_method(component, method_name, params);
+        for (resource, amount) in &expected {
+            builder.take_from_worktop_by_amount(*amount, *resource, |builder, bucket_id| {
+                builder.call_method(account, "deposit", args![Bucket(bucket_id)])
+            });
+        }
+        let transaction = builder
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        let receipt = self.execute(transaction);
+
+        assert!(
+            receipt.result.is_ok(),
+            "Expected exactly {} on the worktop after {} of component {}: {:?}",
+            expected
+                .iter()
+                .map(|(resource, amount)| format!("{} of {}", amount, resource))
+                .collect::<Vec<String>>()
+                .join(", "),
+            method_name,
+            component,
+            receipt.result
+        );
+        receipt
+    }
+
+    /// Asserts that a method can be called by a user holding no badges.
+    ///
+    /// The call is made from a throwaway account, which is neither registered as a user
+    /// nor tracked, and without the fee payer. The current user and the fee payer are
+    /// restored afterwards, even if the call panics.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// env.assert_public_method(component, "update_state", args![1u32]);
+    /// ```
+    pub fn assert_public_method(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        let (key, private_key, account) = self.executor.new_account();
+        self.transaction_count += 1;
+        self.users_pk.insert(account, private_key);
+        let previous_user = self.current_user.replace(User { key, account });
+        let previous_fee_payer = self.fee_payer.take();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            self.call_method(component, method_name, params)
+        }));
+        self.users_pk.remove(&account);
+        self.current_user = previous_user;
+        self.fee_payer = previous_fee_payer;
+        let receipt = match result {
+            Ok(receipt) => receipt,
+            Err(payload) => std::panic::resume_unwind(payload),
+        };
+
+        assert!(
+            receipt.result.is_ok(),
+            "Method {} of component {} is not public: {:?}",
+            method_name,
+            component,
+            receipt.result
+        );
+        receipt
+    }
+
+    /// Creates two fixed supply tokens, e.g. for the pool of an AMM, and returns their
+    /// ResourceAddresses
+    ///
+    /// # Arguments
+    ///
+    /// * `supply_a` - The supply of the first token deposited to the current user
+    /// * `supply_b` - The supply of the second token deposited to the current user
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// env.create_user("acc1");
+    /// let (token_a, token_b) = env.create_token_pair(10000.into(), 5000.into());
+    /// assert_ne!(token_a, token_b);
+    /// ```
+    pub fn create_token_pair(
+        &mut self,
+        supply_a: Decimal,
+        supply_b: Decimal,
+    ) -> (ResourceAddress, ResourceAddress) {
+        (self.create_token(supply_a), self.create_token(supply_b))
+    }
+
+    /// Publishes a given package in a transaction signed by a user.
+    ///
+    /// Packages have no owner in Radix Engine v0.4.1, so the user is only the signer of
+    /// the publishing transaction. Otherwise this behaves like `publish_package`.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`    - The name of the user publishing the package.
+    /// * `name`    - The name of the package.
+    /// * `package` - The package as a binary array.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("publisher");
+    /// env.publish_package_as(
+    ///     "publisher",
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// ```
+    pub fn publish_package_as(&mut self, user: &str, name: &str, package: &[u8]) -> &mut Self {
+        let publisher = self.user(user);
+        let transaction = TransactionBuilder::new()
+            .publish_package(package)
+            .build(self.executor.get_nonce([publisher.key]))
+            .sign(self.account_keys(&publisher));
+        let receipt = self.execute(transaction);
+        if let Err(error) = &receipt.result {
+            panic!("Cannot publish package {}: {:?}", name, error);
+        }
+        let package_addr = receipt.new_package_addresses[0];
+        self.packages.insert(String::from(name), package_addr);
+        self.packages_code
+            .insert(String::from(name), package.to_vec());
+
+        if self.current_package.is_none() {
+            self.current_package = Some(package_addr);
+        }
+        self
+    }
+
+    /// Asserts that the data of a non-fungible equals the expected data.
+    ///
+    /// On mismatch, both values are printed with `{:#?}`, one field per line, so the
+    /// differing field stands out.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The ResourceAddress of the non-fungible resource
+    /// * `id`       - The id of the non-fungible
+    /// * `expected` - The expected data
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// #[derive(NonFungibleData, PartialEq, Debug)]
+    /// struct Character {
+    ///     name: String,
+    ///     #[scrypto(mutable)]
+    ///     level: u8,
+    /// }
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    /// env.create_user("acc1");
+    ///
+    /// let id = NonFungibleId::from_u32(1);
+    /// let characters = env.create_resource(|config| {
+    ///     config.non_fungible().initial_non_fungible_supply(vec![(
+    ///         id.clone(),
+    ///         Character { name: "Hero".to_owned(), level: 1 },
+    ///     )])
+    /// });
+    ///
+    /// env.assert_nf_data_eq(characters, &id, Character { name: "Hero".to_owned(), level: 1 });
+    /// ```
+    pub fn assert_nf_data_eq<T: NonFungibleData + PartialEq + fmt::Debug>(
+        &self,
+        resource: ResourceAddress,
+        id: &NonFungibleId,
+        expected: T,
+    ) {
+        let actual: T = self
+            .non_fungible_data(resource, id)
+            .unwrap_or_else(|error| panic!("{}", error));
+        assert!(
+            actual == expected,
+            "Data of non-fungible {} of {} doesn't match\nactual: {:#?}\nexpected: {:#?}",
+            id,
+            resource,
+            actual,
+            expected
+        );
+    }
+
+    /// Create a test user from an existing key pair, e.g. keys generated by external
+    /// tooling.
+    ///
+    /// The account is funded with XRD from the system faucet, like the accounts of
+    /// `create_user`, and its withdrawals require a signature of the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`        - The name of the user.
+    /// * `public_key`  - The public key of the user.
+    /// * `private_key` - The private key of the user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let private_key = EcdsaPrivateKey::from_bytes(&[1u8; 32]).unwrap();
+    /// let public_key = private_key.public_key();
+    /// let user = env.create_user_with_key("imported", public_key, private_key);
+    /// assert_eq!(user.key, public_key);
+    /// ```
+    pub fn create_user_with_key(
+        &mut self,
+        name: &str,
+        public_key: EcdsaPublicKey,
+        private_key: EcdsaPrivateKey,
+    ) -> User {
+        let withdraw_rule = rule!(require(NonFungibleAddress::from_public_key(&public_key)));
+        let transaction = TransactionBuilder::new()
+            .call_method(SYSTEM_COMPONENT, "free_xrd", vec![])
+            .take_from_worktop(RADIX_TOKEN, |builder, bucket_id| {
+                builder.new_account_with_resource(&withdraw_rule, bucket_id)
+            })
+            .build(self.executor.get_nonce([public_key]))
+            .sign([&private_key]);
+        let receipt = self.execute(transaction);
+        if let Err(error) = &receipt.result {
+            panic!("Cannot create the account of user {}: {:?}", name, error);
+        }
+        let account = receipt.new_component_addresses[0];
+
+        let usr = User {
+            key: public_key,
+            account,
+        };
+        self.users.insert(String::from(name), usr);
+        self.users_pk.insert(account, private_key);
+        if self.current_user.is_none() {
+            self.current_user = Some(usr);
+        }
+        usr
+    }
+
+    /// Builds a custom manifest, signs it like the call helpers and executes it.
+    ///
+    /// Unless the manifest already deposits the whole worktop somewhere, e.g. with
+    /// `TransactionBuilderExt::deposit_all_to`, the leftover resources are deposited to
+    /// the current user, or where `with_deposit_to` and `without_deposit` say.
+    ///
+    /// # Arguments
+    ///
+    /// * `build` - Adds the instructions of the manifest to a builder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use radix_engine::transaction::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let other = env.create_user("acc2");
+    ///
+    /// let receipt = env.execute_custom(|builder| {
+    ///     builder
+    ///         .withdraw_from_account_by_amount(10.into(), RADIX_TOKEN, user.account)
+    ///         .deposit_all_to(other.account)
+    /// });
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn execute_custom(
+        &mut self,
+        build: impl FnOnce(&mut TransactionBuilder) -> &mut TransactionBuilder,
+    ) -> Receipt {
+        let deposit_target = self.take_deposit_target();
+        let user = self.get_current_user().0;
+        let mut builder = TransactionBuilder::new();
+        build(&mut builder);
+        let mut transaction = builder.build(self.executor.get_nonce([user.key]));
+
+        let deposits_all = transaction.instructions.iter().any(|instruction| {
+            matches!(instruction, Instruction::CallMethodWithAllResources { .. })
+        });
+        if !deposits_all {
+            let deposit = TransactionBuilder::new()
+                .deposit_leftovers(deposit_target, user.account)
+                .build(0);
+            // insert before the trailing nonce instruction
+            let nonce = transaction.instructions.pop().unwrap();
+            transaction.instructions.extend(
+                deposit
+                    .instructions
+                    .into_iter()
+                    .filter(|instruction| !matches!(instruction, Instruction::Nonce { .. })),
+            );
+            transaction.instructions.push(nonce);
+        }
+        let transaction = transaction.sign(self.signing_keys());
+        self.execute(transaction)
+    }
+
+    /// Moves the ledger forward by a number of epochs.
+    ///
+    /// # Arguments
+    ///
+    /// * `epochs` - The number of epochs to advance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.advance_epoch(10);
+    /// assert_eq!(env.executor.substate_store().get_epoch(), 10);
+    /// ```
+    pub fn advance_epoch(&mut self, epochs: u64) -> &mut Self {
+        let store = self.executor.substate_store_mut();
+        let epoch = store.get_epoch();
+        store.set_epoch(epoch + epochs);
+        self
+    }
+
+    /// Moves the ledger forward by a number of epochs, then makes a method call, e.g. to
+    /// claim rewards after a vesting period.
+    ///
+    /// # Arguments
+    ///
+    /// * `epochs`      - The number of epochs to advance
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let receipt = env.advance_and_call(5, user.account, "balance", args![RADIX_TOKEN]);
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn advance_and_call(
+        &mut self,
+        epochs: u64,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        self.advance_epoch(epochs);
+        self.call_method(component, method_name, params)
+    }
+
+    /// Returns the number of transactions executed by the test environment.
+    ///
+    /// This includes the transactions publishing packages and creating accounts, which
+    /// the executor runs directly, but not the transactions rejected at validation or the
+    /// balance reads of `get_amount_for_rd`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.create_token(10000.into());
+    ///
+    /// assert_eq!(env.transaction_count(), 2);
+    /// ```
+    pub fn transaction_count(&self) -> u64 {
+        self.transaction_count
+    }
+
+    /// Creates a test user for each stake, funded with that stake of a governance token
+    /// transferred from the current user.
+    ///
+    /// Panics, with nothing created, if the current user holds less of the token than
+    /// the stakes add up to.
+    ///
+    /// # Arguments
+    ///
+    /// * `token`  - The governance token.
+    /// * `stakes` - The name and stake of each voter.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("dao");
+    /// let token = env.create_token(1000.into());
+    ///
+    /// let voters = env.create_voters(token, &[("alice", 100.into()), ("bob", 250.into())]);
+    /// assert_eq!(env.get_amount_for_rd(voters[1].account, token), 250.into());
+    /// ```
+    pub fn create_voters(
+        &mut self,
+        token: ResourceAddress,
+        stakes: &[(&str, Decimal)],
+    ) -> Vec<User> {
+        let account = self.get_current_user().0.account;
+
+        let mut required = Decimal::zero();
+        for (_, stake) in stakes {
+            required = required + *stake;
+        }
+        let available = self.get_amount_for_rd(account, token);
+        if available < required {
+            panic!(
+                "{}",
+                TestEnvError::InsufficientBalance {
+                    resource: token,
+                    required,
+                    available,
+                }
+            );
+        }
+
+        let mut voters = Vec::new();
+        for (name, stake) in stakes {
+            let voter = self.create_user(name);
+            self.transfer_resource(*stake, &token, &voter);
+            voters.push(voter);
+        }
+        voters
+    }
+
+    /// Returns the access rule of every method of a component, rendered as text.
+    ///
+    /// The methods are read from the blueprint ABI. A method no rule protects maps to
+    /// `AllowAll`; the rules of several access rule layers are joined with `&&`.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The ComponentAddress of the component
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// let rules = env.access_rules(component);
+    /// assert_eq!(rules["update_state"], "AllowAll");
+    /// assert_ne!(rules["protected_update_state"], "AllowAll");
+    /// ```
+    pub fn access_rules(&mut self, component: ComponentAddress) -> HashMap<String, String> {
+        let (package, blueprint_name) = self.component_blueprint(component);
+        let abi = self.blueprint_abi(package, &blueprint_name);
+        let layers = self.get_component(component).access_rules().clone();
+
+        let mut rules = HashMap::new();
+        for method in abi.methods {
+            let protections: Vec<String> = layers
+                .iter()
+                .map(|access_rules| access_rules.get(&method.name))
+                .filter(|rule| !matches!(rule, AccessRule::AllowAll))
+                .map(|rule| format!("{:?}", rule))
+                .collect();
+            let rendered = if protections.is_empty() {
+                format!("{:?}", AccessRule::AllowAll)
+            } else {
+                protections.join(" && ")
+            };
+            rules.insert(method.name, rendered);
+        }
+        rules
+    }
+
+    /// Returns the resource address of XRD, the same as `RADIX_TOKEN`.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// assert_eq!(env.get_amount_for_rd(user.account, env.xrd()), 1000000.into());
+    /// ```
+    pub fn xrd(&self) -> ResourceAddress {
+        RADIX_TOKEN
+    }
+
+    /// Returns the resource address of the virtual ECDSA signature badges, the same as
+    /// `ECDSA_TOKEN`.
+    ///
+    /// Each signer of a transaction gets a proof of the badge whose id is its public key,
+    /// so an access rule can require a given user's signature.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let env = TestEnv::new(&mut ledger);
+    ///
+    /// assert_eq!(env.ecdsa_badge(), ECDSA_TOKEN);
+    /// ```
+    pub fn ecdsa_badge(&self) -> ResourceAddress {
+        ECDSA_TOKEN
+    }
+
+    /// Returns the resource address of the system badge, the same as `SYSTEM_TOKEN`.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let env = TestEnv::new(&mut ledger);
+    ///
+    /// assert_eq!(env.system_badge(), SYSTEM_TOKEN);
+    /// ```
+    pub fn system_badge(&self) -> ResourceAddress {
+        SYSTEM_TOKEN
+    }
+
+    /// Makes a method call and returns true if the transaction was accepted and succeeded
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// assert!(env.did_succeed(user.account, "balance", args![RADIX_TOKEN]));
+    /// assert!(!env.did_succeed(user.account, "no_such_method", vec![]));
+    /// ```
+    pub fn did_succeed(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> bool {
+        match self.try_call_method(component, method_name, params) {
+            Ok(receipt) => receipt.result.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the metadata of a resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The ResourceAddress of the resource
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let token = env.create_resource(|config| config.metadata("name", "Test token"));
+    ///
+    /// assert_eq!(env.resource_metadata(token)["name"], "Test token");
+    /// ```
+    pub fn resource_metadata(&self, resource: ResourceAddress) -> HashMap<String, String> {
+        let resource_manager: radix_engine::model::ResourceManager = match self
+            .executor
+            .substate_store()
+            .get_decoded_substate(&resource)
+        {
+            Some((resource_manager, _)) => resource_manager,
+            None => panic!("No resource {} found.", resource),
+        };
+        resource_manager.metadata().clone()
+    }
+
+    /// Sets a metadata entry of a resource, with a proof of the badge allowed to update
+    /// its metadata, and returns the Receipt.
+    ///
+    /// The update goes through a built-in blueprint, since the resource can only be
+    /// updated from within a component. Its package is compiled and published by the
+    /// first call. Only available with the `builtin-blueprints` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource`       - The ResourceAddress of the resource
+    /// * `key`            - The metadata key
+    /// * `value`          - The new value
+    /// * `metadata_badge` - The badge of the current user allowed to update the metadata
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let badge = env.create_badge(1.into());
+    /// let token = env.create_resource(|config| {
+    ///     config
+    ///         .metadata("name", "Test token")
+    ///         .updateable_metadata(rule!(require(badge)), LOCKED)
+    /// });
+    ///
+    /// let icon_url = "https://example.com/icon.png";
+    /// let receipt = env.set_resource_metadata(token, "icon_url", icon_url, badge);
+    /// assert!(receipt.result.is_ok());
+    /// assert_eq!(env.resource_metadata(token)["icon_url"], icon_url);
+    /// ```
+    #[cfg(feature = "builtin-blueprints")]
+    pub fn set_resource_metadata(
+        &mut self,
+        resource: ResourceAddress,
+        key: &str,
+        value: &str,
+        metadata_badge: ResourceAddress,
+    ) -> Receipt {
+        let package_address = self.builtin_package("metadata", || {
+            compile_package!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/metadata/"))
+        });
+
+        let user = self.get_current_user().0;
+        let transaction = TransactionBuilder::new()
+            .call_method(user.account, "create_proof", args![metadata_badge])
+            .pop_from_auth_zone(|builder, proof_id| {
+                builder.call_function(
+                    package_address,
+                    "MetadataSetter",
+                    "set_metadata",
+                    vec![
+                        scrypto_encode(&resource),
+                        scrypto_encode(&key.to_owned()),
+                        scrypto_encode(&value.to_owned()),
+                        scrypto_encode(&Proof(proof_id)),
+                    ],
+                )
+            })
+            .build(self.executor.get_nonce([user.key]))
+            .sign(self.signing_keys());
+        self.execute(transaction)
+    }
+}
+
+impl<'l, L: SubstateStore + QueryableSubstateStore> TestEnv<'l, L> {
+    /// Returns every vault of a component with its resource and balance.
+    ///
+    /// Vaults are found in the component state and in its lazy maps, including nested ones.
+    /// The balance of a non-fungible vault is the number of non-fungibles it holds.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The ComponentAddress of the component or account
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let token = env.create_token(10000.into());
+    ///
+    /// let vaults = env.component_vaults(user.account);
+    /// assert!(vaults.contains(&(token, 10000.into())));
+    /// ```
+    pub fn component_vaults(&self, component: ComponentAddress) -> Vec<(ResourceAddress, Decimal)> {
+        let state = self.get_component(component).state().to_vec();
+        let mut vaults = Vec::new();
+        self.collect_vaults(component, &state, &mut vaults);
+        vaults
+            .iter()
+            .map(|vault| (vault.resource_address(), vault.total_amount()))
+            .collect()
+    }
+
+    /// Returns every resource held by the current user's account with its amount.
+    ///
+    /// The amount of a non-fungible resource is the number of non-fungibles held.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// let token = env.create_token(10000.into());
+    ///
+    /// let portfolio = env.portfolio();
+    /// assert_eq!(portfolio[&RADIX_TOKEN], 1000000.into());
+    /// assert_eq!(portfolio[&token], 10000.into());
+    /// ```
+    pub fn portfolio(&self) -> HashMap<ResourceAddress, Decimal> {
+        let account = self.get_current_user().0.account;
+        self.holdings(account)
+    }
+
+    /// Asserts that two accounts hold the same resources in the same amounts.
+    ///
+    /// The first resource whose amounts differ is reported, a resource held by only
+    /// one of the accounts counting as zero in the other.
+    ///
+    /// # Arguments
+    ///
+    /// * `a` - The first account.
+    /// * `b` - The second account.
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let alice = env.create_user("alice");
+    /// let bob = env.create_user("bob");
+    /// env.assert_portfolios_equal(alice.account, bob.account);
+    /// ```
+    pub fn assert_portfolios_equal(&self, a: ComponentAddress, b: ComponentAddress) {
+        let holdings_a = self.holdings(a);
+        let holdings_b = self.holdings(b);
+        let resources = holdings_a.keys().chain(
+            holdings_b
+                .keys()
+                .filter(|resource| !holdings_a.contains_key(resource)),
+        );
+        for resource in resources {
+            let amount_a = holdings_a
+                .get(resource)
+                .cloned()
+                .unwrap_or_else(Decimal::zero);
+            let amount_b = holdings_b
+                .get(resource)
+                .cloned()
+                .unwrap_or_else(Decimal::zero);
+            assert!(
+                amount_a == amount_b,
+                "Portfolios differ on {}: {} holds {} but {} holds {}",
+                resource,
+                a,
+                amount_a,
+                b,
+                amount_b
+            );
+        }
+    }
+
+    /// Calls a function that instantiates a component, panics if it failed and returns the
+    /// new component, the resources deposited to the current user and the receipt.
+    ///
+    /// The deposited resources are the amounts the deposit target gained in the call,
+    /// e.g. the admin badge returned along with the component, in the order of its vaults.
+    /// The target is the current user's account unless `with_deposit_to` or
+    /// `without_deposit` was called before. Like `call_method_capture_returns`, this
+    /// reports net amounts, so resources the function also took from the target are
+    /// netted out.
+    ///
+    /// # Arguments
+    ///
+    /// * `blueprint_name` - The name of the blueprint
+    /// * `function_name`  - The name of the function to call
+    /// * `params`         - The arguments to pass into the function
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// let (component, deposited, _) = env.instantiate_capturing("Hello", "instantiate", vec![]);
+    /// let (admin_badge, amount) = deposited[0];
+    /// assert_eq!(amount, 1.into());
+    ///
+    /// let receipt = env.call_method_auth(
+    ///     component,
+    ///     "protected_update_state",
+    ///     admin_badge,
+    ///     vec![scrypto_encode(&2u32)],
+    /// );
+    /// assert!(receipt.result.is_ok());
+    /// ```
+    pub fn instantiate_capturing(
+        &mut self,
+        blueprint_name: &str,
+        function_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> (ComponentAddress, Vec<(ResourceAddress, Decimal)>, Receipt) {
+        let account = match self.deposit_target {
+            Some(DepositTarget::Account(account)) => account,
+            _ => self.get_current_user().0.account,
+        };
+        let before = self.holdings(account);
+
+        let receipt = self.call_function(blueprint_name, function_name, params);
+        assert!(
+            receipt.result.is_ok(),
+            "Failed to call {}::{}: {:?}",
+            blueprint_name,
+            function_name,
+            receipt.result
+        );
+        let component = *receipt.new_component_addresses.first().unwrap_or_else(|| {
+            panic!("{}::{} created no component", blueprint_name, function_name)
+        });
+
+        let deposited = self.gained_since(account, &before);
+        (component, deposited, receipt)
+    }
+
+    /// Makes a method call and returns its Receipt with the resources it returned, as
+    /// the amounts the deposit target gained in the call, in the order of its vaults.
+    ///
+    /// The target is the current user's account unless `with_deposit_to` was called
+    /// before. The v0.4.1 receipt only keeps the ids of the returned buckets, not their
+    /// contents, so an amount is net of what the call took from the same account: a
+    /// method withdrawing from the caller's account and returning the same resource
+    /// reports the difference, or nothing if it returned as much as it took. Nothing is
+    /// reported for a failed call.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let other = env.create_user("acc2");
+    ///
+    /// let (receipt, returned) = env.with_deposit_to(other.account).call_method_capture_returns(
+    ///     user.account,
+    ///     "withdraw_by_amount",
+    ///     args![Decimal::from(500), RADIX_TOKEN],
+    /// );
+    /// assert!(receipt.result.is_ok());
+    /// assert_eq!(returned, vec![(RADIX_TOKEN, 500.into())]);
+    /// ```
+    pub fn call_method_capture_returns(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> (Receipt, Vec<(ResourceAddress, Decimal)>) {
+        let account = match self.deposit_target {
+            Some(DepositTarget::Account(account)) => account,
+            _ => self.get_current_user().0.account,
+        };
+        let before = self.holdings(account);
+
+        let receipt = self.call_method(component, method_name, params);
+        let returned = self.gained_since(account, &before);
+        (receipt, returned)
+    }
+
+    /// Returns the amount of a resource held in the vaults of a component, zero if it
+    /// holds none.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The ComponentAddress of the component
+    /// * `resource`  - The ResourceAddress of the resource, e.g. a minter badge
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let token = env.create_token(100.into());
+    /// assert_eq!(env.component_holds(user.account, token), 100.into());
+    /// ```
+    pub fn component_holds(
+        &self,
+        component: ComponentAddress,
+        resource: ResourceAddress,
+    ) -> Decimal {
+        self.holdings(component)
+            .get(&resource)
+            .cloned()
+            .unwrap_or_else(Decimal::zero)
+    }
+
+    /// Calls a method twice and asserts that the second call left the component as the
+    /// first one did, e.g. for an initialization or claim method, then returns the
+    /// receipt of the second call.
+    ///
+    /// The first call must succeed. The second one may fail, as long as it changes
+    /// nothing in the component: only its state and the balances of its vaults are
+    /// compared. Side effects on accounts or other components, e.g. resources returned
+    /// to the caller again, are not checked, since the ledger can't be snapshot and
+    /// compared as a whole.
+    ///
+    /// # Arguments
+    ///
+    /// * `component`   - The ComponentAddress of the component
+    /// * `method_name` - The name of the method
+    /// * `params`      - A vector of encoded arguments to pass in the method
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    /// let receipt = env.call_function("Hello", "instantiate", vec![]);
+    /// let component = receipt.new_component_addresses[0];
+    ///
+    /// env.assert_component_idempotent(component, "update_state", args![2u32]);
+    /// ```
+    pub fn assert_component_idempotent(
+        &mut self,
+        component: ComponentAddress,
+        method_name: &str,
+        params: Vec<Vec<u8>>,
+    ) -> Receipt {
+        let receipt = self.call_method(component, method_name, params.clone());
+        assert!(
+            receipt.result.is_ok(),
+            "Failed to call {} of component {}: {:?}",
+            method_name,
+            component,
+            receipt.result
+        );
+        let state = self.get_component(component).state().to_vec();
+        let vaults = self.component_vaults(component);
+
+        let receipt = self.call_method(component, method_name, params);
+        assert!(
+            self.get_component(component).state() == state.as_slice(),
+            "Calling {} of component {} again changed its state",
+            method_name,
+            component
+        );
+        let vaults_again = self.component_vaults(component);
+        assert!(
+            vaults_again == vaults,
+            "Calling {} of component {} again changed its vaults from {:?} to {:?}",
+            method_name,
+            component,
+            vaults,
+            vaults_again
+        );
+        receipt
+    }
+
+    /// Returns the resources a component holds more of than before, in the order of its
+    /// vaults, with the amounts gained. Resources not created through the test
+    /// environment are included.
+    fn gained_since(
+        &self,
+        component: ComponentAddress,
+        before: &HashMap<ResourceAddress, Decimal>,
+    ) -> Vec<(ResourceAddress, Decimal)> {
+        let after = self.holdings(component);
+        let mut gained: Vec<(ResourceAddress, Decimal)> = Vec::new();
+        for (resource, _) in self.component_vaults(component) {
+            if gained
+                .iter()
+                .any(|(gained_resource, _)| *gained_resource == resource)
+            {
+                continue;
+            }
+            let amount_before = before.get(&resource).cloned().unwrap_or_else(Decimal::zero);
+            let amount_after = after[&resource];
+            if amount_after > amount_before {
+                gained.push((resource, amount_after - amount_before));
+            }
+        }
+        gained
+    }
+
+    /// Sums the amounts of the vaults of a component by resource.
+    fn holdings(&self, component: ComponentAddress) -> HashMap<ResourceAddress, Decimal> {
+        let mut holdings = HashMap::new();
+        for (resource, amount) in self.component_vaults(component) {
+            let total = holdings.entry(resource).or_insert_with(Decimal::zero);
+            *total = *total + amount;
+        }
+        holdings
+    }
+
+    /// Asserts that an account holds the expected number of non-fungibles of a resource.
+    ///
+    /// The ids held in the vaults of the account are counted, and the resource must be
+    /// a non-fungible one.
+    ///
+    /// # Arguments
+    ///
+    /// * `account`  - The ComponentAddress of the account
+    /// * `resource` - The ResourceAddress of the non-fungible resource
+    /// * `expected` - The expected number of non-fungibles
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// #[derive(NonFungibleData)]
+    /// struct Ticket {
+    ///     seat: u32,
+    /// }
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let tickets = env.create_resource(|config| {
+    ///     config.non_fungible().initial_non_fungible_supply(vec![
+    ///         (NonFungibleId::from_u32(1), Ticket { seat: 1 }),
+    ///         (NonFungibleId::from_u32(2), Ticket { seat: 2 }),
+    ///     ])
+    /// });
+    ///
+    /// env.assert_nf_count(user.account, tickets, 2);
+    /// ```
+    pub fn assert_nf_count(
+        &self,
+        account: ComponentAddress,
+        resource: ResourceAddress,
+        expected: usize,
+    ) {
+        let resource_manager: radix_engine::model::ResourceManager = match self
+            .executor
+            .substate_store()
+            .get_decoded_substate(&resource)
+        {
+            Some((resource_manager, _)) => resource_manager,
+            None => panic!("No resource {} found.", resource),
+        };
+        if !matches!(resource_manager.resource_type(), ResourceType::NonFungible) {
+            panic!("{} is not a non-fungible resource", resource);
+        }
+
+        let state = self.get_component(account).state().to_vec();
+        let mut vaults = Vec::new();
+        self.collect_vaults(account, &state, &mut vaults);
+        let mut count = 0;
+        for vault in vaults
+            .iter()
+            .filter(|vault| vault.resource_address() == resource)
+        {
+            count += vault.total_ids().unwrap().len();
+        }
+        assert_eq!(
+            count, expected,
+            "Expected {} to hold {} non-fungibles of {}, but it holds {}",
+            account, expected, resource, count
+        );
+    }
+
+    /// Reads the value stored for a key in a lazy map of a component, or `None` if the
+    /// key is absent.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The ComponentAddress of the component
+    /// * `field`     - Reads the lazy map from the decoded component state
+    /// * `key`       - The key of the entry
+    ///
+    /// # Examples
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// #[derive(TypeId, Decode)]
+    /// struct AccountState {
+    ///     vaults: LazyMap<ResourceAddress, Vault>,
+    /// }
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// let user = env.create_user("acc1");
+    /// let vault: Option<Vault> = env.kv_store_get(
+    ///     user.account,
+    ///     |state: &AccountState| &state.vaults,
+    ///     &RADIX_TOKEN,
+    /// );
+    /// assert!(vault.is_some());
+    /// ```
+    pub fn kv_store_get<S, K, V>(
+        &self,
+        component: ComponentAddress,
+        field: impl Fn(&S) -> &LazyMap<K, V>,
+        key: &K,
+    ) -> Option<V>
+    where
+        S: Decode,
+        K: Encode + Decode,
+        V: Encode + Decode,
+    {
+        let state: S = self.component_state(component);
+        let lazy_map_id = *ScryptoValue::from_value(field(&state))
+            .lazy_map_ids
+            .iter()
+            .next()
+            .unwrap();
+        let entries = self
+            .executor
+            .substate_store()
+            .get_lazy_map_entries(component, &lazy_map_id);
+        entries.get(&scrypto_encode(key)).map(|data| {
+            scrypto_decode(data).unwrap_or_else(|error| {
+                panic!(
+                    "Cannot decode the lazy map entry of component {}: {:?}",
+                    component, error
+                )
+            })
+        })
+    }
+
+    /// Collects the vaults referenced by an encoded value, following its lazy maps.
+    fn collect_vaults(&self, component: ComponentAddress, data: &[u8], vaults: &mut Vec<Vault>) {
+        let value = ScryptoValue::from_slice(data).unwrap();
+        for vault_id in &value.vault_ids {
+            vaults.push(self.get_vault(component, vault_id));
+        }
+        for lazy_map_id in &value.lazy_map_ids {
+            let entries = self
+                .executor
+                .substate_store()
+                .get_lazy_map_entries(component, lazy_map_id);
+            for data in entries.values() {
+                self.collect_vaults(component, data, vaults);
+            }
+        }
+    }
+
+    /// Reads a vault of a component.
+    fn get_vault(&self, component: ComponentAddress, vault_id: &VaultId) -> Vault {
+        match self
+            .executor
+            .substate_store()
+            .get_decoded_child_substate(&component, vault_id)
+        {
+            Some((vault, _)) => vault,
+            None => panic!("No vault {:?} found in component {}.", vault_id, component),
+        }
+    }
+}
+
+impl<'l> TestEnv<'l, InMemorySubstateStore> {
+    /// Resets the ledger to a fresh bootstrap while keeping the published packages.
+    ///
+    /// Every package is published again from its cached code, so package addresses
+    /// may change but names keep resolving. Everything else is cleared: users, the
+    /// current user and their private keys, the fee payer, the tracked components and
+    /// resources, the settings pending for the next call and the transaction count.
+    /// Transactions and receipts recorded before the reset are dropped too, as they
+    /// can't be replayed on the fresh ledger, but recording stays enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    /// use radix_engine::ledger::*;
+    /// use scrypto::prelude::*;
+    ///
+    /// let mut ledger = InMemorySubstateStore::with_bootstrap();
+    /// let mut env = TestEnv::new(&mut ledger);
+    ///
+    /// env.create_user("acc1");
+    /// env.publish_package(
+    ///     "package",
+    ///     include_code!("../tests/assets/hello-world", "hello_world")
+    /// );
+    ///
+    /// env.reset_state();
+    /// assert!(env.users.is_empty());
+    /// env.get_package("package");
+    /// ```
+    pub fn reset_state(&mut self) -> &mut Self {
+        *self.executor.substate_store_mut() = InMemorySubstateStore::with_bootstrap();
+
+        self.users.clear();
+        self.current_user = None;
+        self.users_pk.clear();
+        self.faucet_sink = None;
+        self.components.clear();
+        self.resources = vec![RADIX_TOKEN];
+        self.fee_payer = None;
+        self.extra_keys.clear();
+        self.builtin_packages.clear();
+        self.last_transaction = None;
+        self.deposit_target = None;
+        self.trace_next = false;
+        self.next_label = None;
+        self.transaction_count = 0;
+        if let Some(log) = self.transaction_log.as_mut() {
+            *log = TransactionLog::default();
+        }
+        if let Some(history) = self.receipt_history.as_mut() {
+            *history = ReceiptHistory::new();
+        }
+
+        let current_package_name = self.current_package.and_then(|current| {
+            self.packages
+                .iter()
+                .find(|(_, &package)| package == current)
+                .map(|(name, _)| name.clone())
+        });
+        self.packages.clear();
+        self.current_package = None;
+
+        for (name, code) in std::mem::take(&mut self.packages_code) {
+            self.publish_package(&name, &code);
+        }
+        if let Some(name) = current_package_name {
+            self.using_package(&name);
+        }
+        self
+    }
+}
+
+/// A test environment owning its in-memory ledger.
+///
+/// It has no ledger lifetime, so it can be returned from a shared setup function. The
+/// test environment itself is borrowed with `env`, which builds its executor over the
+/// owned ledger.
+pub struct OwnedTestEnv {
+    ledger: InMemorySubstateStore,
+    trace: bool,
+    state: Option<EnvState>,
+}
+
+impl OwnedTestEnv {
+    /// Returns a test environment owning a freshly bootstrapped in-memory ledger.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use scrypto_unit::*;
+    ///
+    /// fn setup() -> OwnedTestEnv {
+    ///     let mut owned = OwnedTestEnv::new();
+    ///     owned.env().create_user("acc1");
+    ///     owned
+    /// }
+    ///
+    /// let mut owned = setup();
+    /// let env = owned.env();
+    /// env.get_user("acc1");
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            ledger: InMemorySubstateStore::with_bootstrap(),
+            trace: false,
+            state: Some(EnvState::new()),
+        }
+    }
 
-        receipt
+    /// Returns an owning test environment exactly like `new` but with a tracing executor
+    pub fn new_with_tracing() -> Self {
+        Self {
+            trace: true,
+            ..Self::new()
+        }
+    }
+
+    /// Borrows the test environment, which keeps its users, packages and other state
+    /// from one borrow to the next.
+    pub fn env(&mut self) -> TestEnvGuard<'_> {
+        let state = self
+            .state
+            .take()
+            .expect("The state of the test environment was lost");
+        let executor = TransactionExecutor::new(&mut self.ledger, self.trace);
+        TestEnvGuard {
+            env: Some(TestEnv::from_parts(executor, state)),
+            state: &mut self.state,
+        }
+    }
+}
+
+impl Default for OwnedTestEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A test environment borrowed from an `OwnedTestEnv`, handing its state back when
+/// dropped.
+pub struct TestEnvGuard<'a> {
+    env: Option<TestEnv<'a, InMemorySubstateStore>>,
+    state: &'a mut Option<EnvState>,
+}
+
+impl<'a> std::ops::Deref for TestEnvGuard<'a> {
+    type Target = TestEnv<'a, InMemorySubstateStore>;
+
+    fn deref(&self) -> &Self::Target {
+        self.env.as_ref().unwrap()
+    }
+}
+
+impl<'a> std::ops::DerefMut for TestEnvGuard<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.env.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for TestEnvGuard<'a> {
+    fn drop(&mut self) {
+        if let Some(env) = self.env.take() {
+            *self.state = Some(env.into_parts().1);
+        }
     }
 }
 
@@ -731,3 +5024,477 @@ pub fn return_of_call_method<T: Decode>(receipt: &mut Receipt, method_name: &str
     let encoded = receipt.outputs.swap_remove(instruction_index).raw;
     scrypto_decode(&encoded).unwrap()
 }
+
+/// Renders every output of a transaction as a readable string
+///
+/// Outputs that can't be decoded as SBOR values are rendered as hex.
+///
+/// # Arguments
+///
+/// * `receipt` - The receipt of the transaction
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use radix_engine::ledger::*;
+/// use scrypto::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// let user = env.create_user("acc1");
+/// let receipt = env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+/// for output in decode_outputs(&receipt) {
+///     println!("{}", output);
+/// }
+/// ```
+pub fn decode_outputs(receipt: &Receipt) -> Vec<String> {
+    receipt
+        .outputs
+        .iter()
+        .map(|output| match ScryptoValue::from_slice(&output.raw) {
+            Ok(value) => format!("{:?}", value.dom),
+            Err(_) => output
+                .raw
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Encodes a value, checking in debug builds that it decodes back to the same encoding
+///
+/// A malformed encoding panics at the call site with the concrete type name, instead of
+/// surfacing deep inside the engine as a decode error.
+///
+/// # Arguments
+///
+/// * `value` - The value to encode
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use scrypto::prelude::*;
+///
+/// let encoded = encode_checked(&42u32);
+/// assert_eq!(encoded, scrypto_encode(&42u32));
+/// ```
+pub fn encode_checked<T: Encode + Decode>(value: &T) -> Vec<u8> {
+    let encoded = scrypto_encode(value);
+    if cfg!(debug_assertions) {
+        let type_name = std::any::type_name::<T>();
+        let decoded: T = match scrypto_decode(&encoded) {
+            Ok(decoded) => decoded,
+            Err(error) => panic!("Failed to decode encoded {}: {:?}", type_name, error),
+        };
+        if scrypto_encode(&decoded) != encoded {
+            panic!("Encoding of {} doesn't round-trip", type_name);
+        }
+    }
+    encoded
+}
+
+/// Encodes the address of a non-fungible, for methods expecting a `NonFungibleAddress`
+///
+/// `params!` accepts a `NonFungibleAddress` too, this saves building one by hand.
+///
+/// # Arguments
+///
+/// * `resource` - The resource address of the non-fungible
+/// * `id`       - The id of the non-fungible
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use scrypto::prelude::*;
+///
+/// let id = NonFungibleId::from_u32(1);
+/// let encoded = encode_non_fungible_address(RADIX_TOKEN, id.clone());
+/// assert_eq!(encoded, params!(NonFungibleAddress::new(RADIX_TOKEN, id))[0]);
+/// ```
+pub fn encode_non_fungible_address(resource: ResourceAddress, id: NonFungibleId) -> Vec<u8> {
+    encode_checked(&NonFungibleAddress::new(resource, id))
+}
+
+/// Encodes a list of arguments for `call_function` and `call_method`
+///
+/// Every argument is encoded with `encode_checked`.
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use scrypto::prelude::*;
+///
+/// let params = params!(42u32, String::from("hello"));
+/// assert_eq!(params[0], scrypto_encode(&42u32));
+/// ```
+#[macro_export]
+macro_rules! params {
+    ($($arg:expr),* $(,)?) => {
+        vec![$($crate::encode_checked(&$arg)),*]
+    };
+}
+
+/// Asserts that a transaction failed because of an authorization check
+///
+/// Fails if the transaction succeeded or failed for any other reason, e.g. a wrong
+/// number of arguments.
+///
+/// # Arguments
+///
+/// * `receipt` - The receipt of the transaction
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use radix_engine::ledger::*;
+/// use scrypto::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// env.create_user("acc1");
+/// env.publish_package(
+///     "package",
+///     include_code!("../tests/assets/hello-world", "hello_world")
+/// );
+///
+/// let receipt = env.call_function("Hello", "instantiate", vec![]);
+/// let component = receipt.new_component_addresses[0];
+///
+/// let receipt = env.call_method(component, "protected_update_state", vec![scrypto_encode(&2u32)]);
+/// assert_auth_failure(&receipt);
+/// ```
+pub fn assert_auth_failure(receipt: &Receipt) {
+    match &receipt.result {
+        Err(RuntimeError::AuthorizationError { .. }) => {}
+        Err(error) => panic!("Expected an authorization failure, got {:?}", error),
+        Ok(()) => panic!("Expected an authorization failure, but the transaction succeeded"),
+    }
+}
+
+/// Asserts that a transaction created exactly the expected number of components and
+/// resources
+///
+/// # Arguments
+///
+/// * `receipt`    - The receipt of the transaction
+/// * `components` - The expected number of new components
+/// * `resources`  - The expected number of new resources
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use radix_engine::ledger::*;
+/// use scrypto::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// env.create_user("acc1");
+/// env.publish_package(
+///     "package",
+///     include_code!("../tests/assets/hello-world", "hello_world")
+/// );
+///
+/// let receipt = env.call_function("Hello", "instantiate", vec![]);
+/// assert_created(&receipt, 1, 1);
+/// ```
+pub fn assert_created(receipt: &Receipt, components: usize, resources: usize) {
+    assert_eq!(
+        receipt.new_component_addresses.len(),
+        components,
+        "Expected {} new components, got {:?}",
+        components,
+        receipt.new_component_addresses
+    );
+    assert_eq!(
+        receipt.new_resource_addresses.len(),
+        resources,
+        "Expected {} new resources, got {:?}",
+        resources,
+        receipt.new_resource_addresses
+    );
+}
+
+/// Returns the index of the instruction that failed, or `None` if the transaction
+/// succeeded
+///
+/// The index points into `receipt.validated_transaction.instructions`. The engine stops
+/// at the first failing instruction, after recording an output for each instruction
+/// that succeeded.
+///
+/// # Arguments
+///
+/// * `receipt` - The receipt of the transaction
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use radix_engine::ledger::*;
+/// use scrypto::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// let user = env.create_user("acc1");
+/// let receipt = env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+/// assert_eq!(failed_instruction_index(&receipt), None);
+/// ```
+pub fn failed_instruction_index(receipt: &Receipt) -> Option<usize> {
+    match receipt.result {
+        Ok(()) => None,
+        Err(_) => Some(receipt.outputs.len()),
+    }
+}
+
+/// Asserts that a transaction failed at the given instruction
+///
+/// # Arguments
+///
+/// * `receipt` - The receipt of the transaction
+/// * `index`   - The index of the instruction expected to fail
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use radix_engine::ledger::*;
+/// use scrypto::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// let user = env.create_user("acc1");
+/// let receipt = env.call_method(user.account, "no_such_method", vec![]);
+/// assert_instruction_failed(&receipt, 0);
+/// ```
+pub fn assert_instruction_failed(receipt: &Receipt, index: usize) {
+    match failed_instruction_index(receipt) {
+        Some(failed) => assert_eq!(
+            failed, index,
+            "Expected instruction {} to fail, but instruction {} failed: {:?}",
+            index, failed, receipt.result
+        ),
+        None => panic!(
+            "Expected instruction {} to fail, but the transaction succeeded",
+            index
+        ),
+    }
+}
+
+/// Asserts that a transaction was rejected at validation, before execution
+///
+/// # Arguments
+///
+/// * `result` - The result of a `try_*` call
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use radix_engine::ledger::*;
+/// use scrypto::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// let user = env.create_user("acc1");
+/// let result = env.call_method_unsigned(user.account, "balance", args![RADIX_TOKEN]);
+/// assert_rejected(&result);
+/// ```
+pub fn assert_rejected(result: &Result<Receipt, TransactionValidationError>) {
+    if let Ok(receipt) = result {
+        match &receipt.result {
+            Ok(()) => panic!("Expected the transaction to be rejected, but it succeeded"),
+            Err(error) => panic!(
+                "Expected the transaction to be rejected, but it failed at runtime: {:?}",
+                error
+            ),
+        }
+    }
+}
+
+/// Asserts that a transaction was executed and failed at runtime
+///
+/// # Arguments
+///
+/// * `receipt` - The receipt of the transaction
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use radix_engine::ledger::*;
+/// use scrypto::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// let user = env.create_user("acc1");
+/// let receipt = env.call_method(user.account, "no_such_method", vec![]);
+/// assert_runtime_failure(&receipt);
+/// ```
+pub fn assert_runtime_failure(receipt: &Receipt) {
+    assert!(
+        receipt.result.is_err(),
+        "Expected the transaction to fail at runtime, but it succeeded"
+    );
+}
+
+/// Asserts that two decimals differ by at most a tolerance
+///
+/// # Arguments
+///
+/// * `actual`    - The actual value
+/// * `expected`  - The expected value
+/// * `tolerance` - The largest accepted difference
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use scrypto::prelude::*;
+///
+/// assert_approx_eq(dec!("99.999"), dec!("100"), dec!("0.01"));
+/// ```
+pub fn assert_approx_eq(actual: Decimal, expected: Decimal, tolerance: Decimal) {
+    let difference = if actual > expected {
+        actual - expected
+    } else {
+        expected - actual
+    };
+    assert!(
+        difference <= tolerance,
+        "{} is not within {} of {}: the difference is {}",
+        actual,
+        tolerance,
+        expected,
+        difference
+    );
+}
+
+/// Runs a test body once with a normal test environment and once with a tracing one,
+/// each on a fresh ledger
+///
+/// The body gets a `&mut TestEnv`. A panic in the body is raised again with the mode of
+/// the run prepended to its message, so a failing test tells which one failed.
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use scrypto::prelude::*;
+///
+/// for_each_executor!(|env| {
+///     let user = env.create_user("acc1");
+///     let receipt = env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+///     assert!(receipt.result.is_ok());
+/// });
+/// ```
+#[macro_export]
+macro_rules! for_each_executor {
+    (|$env:ident| $body:expr) => {
+        for &tracing in &[false, true] {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let mut ledger = $crate::prelude::InMemorySubstateStore::with_bootstrap();
+                let mut test_env = if tracing {
+                    $crate::TestEnv::new_with_tracing(&mut ledger)
+                } else {
+                    $crate::TestEnv::new(&mut ledger)
+                };
+                let $env = &mut test_env;
+                $body;
+            }));
+            if let Err(payload) = result {
+                let message = match payload.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => match payload.downcast_ref::<String>() {
+                        Some(message) => message.clone(),
+                        None => String::from("Box<dyn Any>"),
+                    },
+                };
+                panic!("Failed with tracing = {}: {}", tracing, message);
+            }
+        }
+    };
+}
+
+/// Asserts that a transaction failed on a panic whose message contains the expected text
+///
+/// Scrypto blueprints log the message of a panic, e.g. of `assert!(cond, "message")`,
+/// as an error before aborting, and the message is read back from `receipt.logs`.
+///
+/// # Arguments
+///
+/// * `receipt`  - The receipt of the transaction
+/// * `expected` - The text expected in the panic message
+///
+/// # Examples
+/// ```
+/// use scrypto_unit::*;
+/// use radix_engine::ledger::*;
+/// use scrypto::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// env.create_user("acc1");
+/// env.publish_package(
+///     "package",
+///     include_code!("../tests/assets/hello-world", "hello_world")
+/// );
+///
+/// let receipt = env.call_function("Hello", "instantiate", vec![]);
+/// let component = receipt.new_component_addresses[0];
+/// env.call_method(component, "update_state", vec![scrypto_encode(&5u32)]);
+///
+/// let receipt = env.call_method(component, "assert_state", vec![scrypto_encode(&7u32)]);
+/// assert_panic_message(&receipt, "Unexpected state");
+/// ```
+pub fn assert_panic_message(receipt: &Receipt, expected: &str) {
+    assert!(
+        receipt.result.is_err(),
+        "Expected the transaction to panic with {:?}, but it succeeded",
+        expected
+    );
+    let panics: Vec<&String> = receipt
+        .logs
+        .iter()
+        .map(|(_, message)| message)
+        .filter(|message| message.starts_with("Panicked at"))
+        .collect();
+    assert!(
+        panics.iter().any(|message| message.contains(expected)),
+        "Expected a panic with {:?}, but the transaction failed with {:?} and panics {:?}",
+        expected,
+        receipt.result,
+        panics
+    );
+}
+
+/// The items commonly used in tests, so a single `use scrypto_unit::prelude::*;` covers
+/// the test environment, the ledger and the Scrypto types.
+///
+/// # Examples
+///
+/// ```
+/// use scrypto_unit::prelude::*;
+///
+/// let mut ledger = InMemorySubstateStore::with_bootstrap();
+/// let mut env = TestEnv::new(&mut ledger);
+///
+/// let user = env.create_user("acc1");
+/// let receipt = env.call_method(user.account, "balance", params![RADIX_TOKEN]);
+/// assert!(receipt.result.is_ok());
+/// ```
+pub mod prelude {
+    pub use crate::{
+        assert_approx_eq, assert_auth_failure, assert_created, assert_instruction_failed,
+        assert_panic_message, assert_rejected, assert_runtime_failure, decode_outputs,
+        encode_checked, encode_non_fungible_address, failed_instruction_index, for_each_executor,
+        params, return_of_call_function, return_of_call_method, DepositTarget, OwnedTestEnv,
+        ReceiptHistory, ResourceConfig, TestEnv, TestEnvError, TestEnvGuard, TransactionBuilderExt,
+        TransactionLog, User,
+    };
+    pub use radix_engine::ledger::{InMemorySubstateStore, SubstateStore};
+    pub use radix_engine::model::Receipt;
+    pub use radix_engine::transaction::TransactionBuilder;
+    pub use scrypto::prelude::*;
+}
@@ -42,5 +42,15 @@ blueprint! {
             self.state = new_state;
             old_state
         }
+
+        /// Panics if the state isn't the expected one, for in-transaction assertions.
+        pub fn assert_state(&self, expected: u32) {
+            assert_eq!(self.state, expected, "Unexpected state");
+        }
+
+        /// Returns the amount of resource a proof is for.
+        pub fn proof_amount(&self, proof: Proof) -> Decimal {
+            proof.amount()
+        }
     }
 }
@@ -1,6 +1,7 @@
 extern crate radix_engine;
 
 use radix_engine::ledger::*;
+use radix_engine::transaction::TransactionBuilder;
 use scrypto::prelude::*;
 use scrypto_unit::*;
 
@@ -30,6 +31,21 @@ fn test_get_user() {
     test_env.get_user("alice");
 }
 
+#[test]
+fn test_user_by_value() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("alice");
+    let token = test_env.create_token(dec!("10000"));
+    let bob = test_env.create_user("bob");
+
+    let user = test_env.user("bob");
+    assert_eq!(user, bob);
+    let transfer_receipt = test_env.transfer_resource(dec!("10"), &token, &user);
+    assert!(transfer_receipt.result.is_ok());
+}
+
 #[test]
 fn test_acting_as() {
     let mut ledger = InMemorySubstateStore::with_bootstrap();
@@ -178,3 +194,2556 @@ fn test_create_token_send_amount() {
     //TODO: assert balance of user before->after using test_env.get_amount_for_rd()
 }
 
+#[test]
+fn test_replay_transaction_log() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.record_transactions();
+    test_env.create_user("admin");
+    let user = test_env.create_user("user");
+    let token = test_env.create_token(dec!("10000"));
+    test_env.transfer_resource(dec!("10"), &token, &user);
+    let log = test_env.transaction_log.clone().unwrap();
+    assert_eq!(log.len(), 2);
+
+    // accounts are not part of the log, so they are created again in the same order
+    let mut other_ledger = InMemorySubstateStore::with_bootstrap();
+    let mut other_env = TestEnv::new(&mut other_ledger);
+    other_env.create_user("admin");
+    other_env.create_user("user");
+
+    let receipts = other_env.replay(&log);
+    assert_eq!(receipts.len(), 2);
+    assert!(receipts.iter().all(|receipt| receipt.result.is_ok()));
+}
+
+#[test]
+fn test_create_badge() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let badge = test_env.create_badge(dec!("1"));
+
+    assert_eq!(test_env.get_amount_for_rd(admin.account, badge), dec!("1"));
+}
+
+#[test]
+fn test_component_func_auth_multi() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    test_env.create_user("user");
+
+    test_env.acting_as("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+    let admin_badge = instantiate_receipt.new_resource_addresses[0];
+    let other_badge = test_env.create_badge(dec!("1"));
+
+    let auth_method_receipt = test_env.call_method_auth_multi(
+        hello_component,
+        "protected_update_state",
+        &[admin_badge, other_badge],
+        vec![scrypto_encode(&42u32)],
+    );
+    assert!(auth_method_receipt.result.is_ok());
+
+    // the user holds none of the badges, so the first proof creation fails
+    test_env.acting_as("user");
+    let unauthorized_receipt = test_env.call_method_auth_multi(
+        hello_component,
+        "protected_update_state",
+        &[admin_badge, other_badge],
+        vec![scrypto_encode(&42u32)],
+    );
+    assert!(unauthorized_receipt.result.is_err());
+}
+
+#[test]
+fn test_reset_state() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    test_env.reset_state();
+    assert!(test_env.users.is_empty());
+    assert!(test_env.current_user.is_none());
+    assert_eq!(
+        test_env.get_current_package(),
+        test_env.get_package(PACKAGE)
+    );
+
+    test_env.create_user("admin");
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+}
+
+#[test]
+fn test_reset_state_clears_everything() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let payer = test_env.create_user("payer");
+    let (_, extra_private_key) = test_env.executor.new_key_pair();
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+    test_env.record_transactions().record_receipts();
+    test_env.create_token(dec!("100"));
+    test_env.fund_xrd("admin", dec!("10"));
+    #[cfg(feature = "builtin-blueprints")]
+    test_env.setup_treasury();
+    test_env
+        .set_fee_payer("payer")
+        .add_key_to_user("admin", extra_private_key)
+        .with_deposit_to(payer.account)
+        .trace_next_call();
+
+    test_env.reset_state();
+    assert!(test_env.users.is_empty());
+    assert!(test_env.users_pk.is_empty());
+    assert!(test_env.current_user.is_none());
+    assert_eq!(test_env.packages.len(), 1);
+    assert_eq!(test_env.packages_code.len(), 1);
+    assert!(test_env
+        .transaction_log
+        .as_ref()
+        .unwrap()
+        .transactions
+        .is_empty());
+    assert!(test_env.receipts().is_empty());
+    assert!(test_env.last_manifest().is_empty());
+    assert!(test_env.tracked_components().is_empty());
+    assert_eq!(test_env.all_resources(), vec![RADIX_TOKEN]);
+    // only the package has been published again
+    assert_eq!(test_env.transaction_count(), 1);
+
+    // no fee payer, extra key or deposit target is left
+    let admin_again = test_env.create_user("admin");
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+    assert_eq!(
+        instantiate_receipt.validated_transaction.signers,
+        vec![admin_again.key]
+    );
+    let admin_badge = instantiate_receipt.new_resource_addresses[0];
+    assert_eq!(
+        test_env.get_amount_for_rd(admin_again.account, admin_badge),
+        dec!("1")
+    );
+    assert_eq!(test_env.receipts().len(), 2);
+
+    // the faucet sink and the built-in packages are set up again
+    assert!(test_env.fund_xrd("admin", dec!("10")).result.is_ok());
+    #[cfg(feature = "builtin-blueprints")]
+    test_env.setup_treasury();
+}
+
+#[test]
+fn test_component_blueprint() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let (package_address, blueprint_name) = test_env.component_blueprint(hello_component);
+    assert_eq!(package_address, test_env.get_package(PACKAGE));
+    assert_eq!(blueprint_name, BLUEPRINT);
+}
+
+#[test]
+fn test_params() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    assert_eq!(params!(42u32), vec![scrypto_encode(&42u32)]);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", params!());
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let method_receipt = test_env.call_method(hello_component, "update_state", params!(42u32));
+    assert!(method_receipt.result.is_ok());
+}
+
+#[test]
+fn test_publish_packages() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+
+    test_env.publish_packages(&[(PACKAGE, &package[..]), ("other", &package[..])]);
+    assert_eq!(test_env.packages.len(), 2);
+    assert_eq!(
+        test_env.get_current_package(),
+        test_env.get_package(PACKAGE)
+    );
+    assert_ne!(test_env.get_package(PACKAGE), test_env.get_package("other"));
+}
+
+#[test]
+fn test_try_accessors() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    assert_eq!(
+        test_env.try_get_current_user().err(),
+        Some(TestEnvError::NoCurrentUser)
+    );
+    assert_eq!(
+        test_env.try_get_current_package(),
+        Err(TestEnvError::NoCurrentPackage)
+    );
+    assert_eq!(
+        test_env.try_get_user("alice").err(),
+        Some(TestEnvError::UnknownUser(String::from("alice")))
+    );
+    assert_eq!(
+        test_env.try_get_package(PACKAGE),
+        Err(TestEnvError::UnknownPackage(String::from(PACKAGE)))
+    );
+
+    let alice = test_env.create_user("alice");
+    assert_eq!(test_env.try_get_user("alice"), Ok(&alice));
+    assert_eq!(test_env.try_get_current_user().unwrap().0, &alice);
+}
+
+#[test]
+fn test_call_method_unsigned() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("user");
+
+    let result = test_env.call_method_unsigned(user.account, "balance", args![RADIX_TOKEN]);
+    assert!(result.is_err());
+
+    let result = test_env.try_call_method(user.account, "balance", args![RADIX_TOKEN]);
+    assert!(result.unwrap().result.is_ok());
+}
+
+#[test]
+fn test_method_requires_auth() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    assert!(test_env.method_requires_auth(hello_component, "protected_update_state"));
+    assert!(!test_env.method_requires_auth(hello_component, "update_state"));
+}
+
+#[derive(TypeId, Decode, PartialEq, Debug)]
+struct HelloState {
+    state: u32,
+    admin_badge: ResourceAddress,
+}
+
+#[test]
+fn test_component_state() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+    let admin_badge = instantiate_receipt.new_resource_addresses[0];
+
+    let state: HelloState = test_env.component_state(hello_component);
+    assert_eq!(state.state, 0);
+
+    test_env.call_method(hello_component, "update_state", params!(42u32));
+    test_env.assert_component_state_eq(
+        hello_component,
+        HelloState {
+            state: 42,
+            admin_badge,
+        },
+    );
+}
+
+#[test]
+fn test_fund_xrd() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let user = test_env.create_user("user");
+    let admin_balance = test_env.get_amount_for_rd(admin.account, RADIX_TOKEN);
+    let user_balance = test_env.get_amount_for_rd(user.account, RADIX_TOKEN);
+
+    let receipt = test_env.fund_xrd("user", dec!("1500000"));
+    assert!(receipt.result.is_ok());
+
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, RADIX_TOKEN),
+        user_balance + dec!("1500000")
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(admin.account, RADIX_TOKEN),
+        admin_balance
+    );
+
+    // the leftover of the two faucet calls goes to the sink, on top of the XRD it
+    // received as a new account
+    let sink = test_env.faucet_sink().unwrap();
+    assert!(!test_env.tracked_components().contains(&sink));
+    assert_eq!(
+        test_env.get_amount_for_rd(sink, RADIX_TOKEN),
+        dec!("1500000")
+    );
+}
+
+#[test]
+fn test_last_manifest() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    assert!(test_env.last_manifest().is_empty());
+
+    let user = test_env.create_user("user");
+    test_env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+
+    let manifest = test_env.last_manifest();
+    assert!(manifest.contains("balance"));
+    assert!(manifest.contains("deposit_batch"));
+}
+
+#[test]
+fn test_create_resource() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let badge = test_env.create_badge(dec!("1"));
+    let token = test_env.create_resource(|config| {
+        config
+            .metadata("name", "Test token")
+            .divisibility(2)
+            .mintable(rule!(require(badge)), LOCKED)
+            .initial_supply(dec!("100"))
+    });
+
+    assert_eq!(
+        test_env.get_amount_for_rd(admin.account, token),
+        dec!("100")
+    );
+}
+
+#[test]
+fn test_deposit_target() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let treasury = test_env.create_user("treasury");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt =
+        test_env
+            .with_deposit_to(treasury.account)
+            .call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+    let admin_badge = instantiate_receipt.new_resource_addresses[0];
+    assert_eq!(
+        test_env.get_amount_for_rd(treasury.account, admin_badge),
+        dec!("1")
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(admin.account, admin_badge),
+        dec!("0")
+    );
+
+    // the admin badge is left on the worktop
+    let failed_receipt = test_env
+        .without_deposit()
+        .call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(failed_receipt.result.is_err());
+
+    // the deposit target only applies to a single call
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+
+    // resource creation deposits the initial supply to the target
+    let token = test_env
+        .with_deposit_to(treasury.account)
+        .create_token(dec!("100"));
+    assert_eq!(
+        test_env.get_amount_for_rd(treasury.account, token),
+        dec!("100")
+    );
+
+    // a helper that deposits nothing drops the pending target
+    test_env
+        .with_deposit_to(treasury.account)
+        .transfer_resource(dec!("10"), &RADIX_TOKEN, &treasury);
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let admin_badge = instantiate_receipt.new_resource_addresses[0];
+    assert_eq!(
+        test_env.get_amount_for_rd(admin.account, admin_badge),
+        dec!("1")
+    );
+}
+
+#[derive(NonFungibleData, Debug, PartialEq)]
+struct Character {
+    name: String,
+    #[scrypto(mutable)]
+    level: u8,
+}
+
+#[test]
+fn test_non_fungible_data() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let id = NonFungibleId::from_u32(1);
+    let characters = test_env.create_resource(|config| {
+        config.non_fungible().initial_non_fungible_supply(vec![(
+            id.clone(),
+            Character {
+                name: "Hero".to_owned(),
+                level: 1,
+            },
+        )])
+    });
+
+    let character: Character = test_env.non_fungible_data(characters, &id).unwrap();
+    assert_eq!(
+        character,
+        Character {
+            name: "Hero".to_owned(),
+            level: 1,
+        }
+    );
+
+    let unknown_id = NonFungibleId::from_u32(2);
+    assert_eq!(
+        test_env.non_fungible_data::<Character>(characters, &unknown_id),
+        Err(TestEnvError::UnknownNonFungible(characters, unknown_id))
+    );
+}
+
+fn setup_owned() -> OwnedTestEnv {
+    let mut owned = OwnedTestEnv::new();
+    let mut test_env = owned.env();
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+    drop(test_env);
+    owned
+}
+
+#[test]
+fn test_owned_test_env() {
+    let mut owned = setup_owned();
+
+    let mut test_env = owned.env();
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+    drop(test_env);
+
+    // the ledger and the state are kept from one borrow to the next
+    let mut test_env = owned.env();
+    assert!(test_env.users.contains_key("admin"));
+    let receipt = test_env.call_method(hello_component, "update_state", args![2u32]);
+    assert!(receipt.result.is_ok());
+}
+
+#[test]
+fn test_assert_auth_failure() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let method_receipt =
+        test_env.call_method(hello_component, "protected_update_state", params!(42u32));
+    assert_auth_failure(&method_receipt);
+}
+
+#[test]
+#[should_panic]
+fn test_assert_auth_failure_other_error() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    // missing argument, not an authorization failure
+    let method_receipt = test_env.call_method(hello_component, "update_state", vec![]);
+    assert_auth_failure(&method_receipt);
+}
+
+#[test]
+fn test_create_user_holding() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let token_a = test_env.create_token(dec!("1000"));
+    let token_b = test_env.create_token(dec!("1000"));
+
+    let trader = test_env
+        .create_user_holding("trader", &[(token_a, dec!("100")), (token_b, dec!("50"))])
+        .unwrap();
+    assert_eq!(
+        test_env.get_amount_for_rd(trader.account, token_a),
+        dec!("100")
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(trader.account, token_b),
+        dec!("50")
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(admin.account, token_a),
+        dec!("900")
+    );
+
+    let result = test_env.create_user_holding("whale", &[(token_a, dec!("5000"))]);
+    assert_eq!(
+        result,
+        Err(TestEnvError::InsufficientBalance {
+            resource: token_a,
+            required: dec!("5000"),
+            available: dec!("900"),
+        })
+    );
+    assert!(!test_env.users.contains_key("whale"));
+}
+
+#[test]
+fn test_get_amount_for_rd_is_not_recorded() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let other = test_env.create_user("acc2");
+    test_env.record_transactions().record_receipts();
+    let transaction_count = test_env.transaction_count();
+
+    // the pending deposit target is still there for the next transaction
+    test_env.with_deposit_to(other.account);
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, RADIX_TOKEN),
+        dec!("1000000")
+    );
+    let token = test_env.create_token(dec!("100"));
+    assert_eq!(
+        test_env.get_amount_for_rd(other.account, token),
+        dec!("100")
+    );
+
+    assert_eq!(test_env.transaction_count(), transaction_count + 1);
+    assert_eq!(test_env.transaction_log.as_ref().unwrap().len(), 1);
+    assert_eq!(test_env.receipts().len(), 1);
+}
+
+#[test]
+fn test_trace_next_call() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt =
+        test_env
+            .trace_next_call()
+            .call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let method_receipt = test_env.call_method(hello_component, "update_state", params!(42u32));
+    assert!(method_receipt.result.is_ok());
+}
+
+#[test]
+fn test_tracked_components() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    test_env.call_function(BLUEPRINT, "instantiate_other", vec![]);
+    test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    // the account funding the faucet leftovers is internal
+    test_env.fund_xrd("admin", dec!("10"));
+
+    let components = test_env.tracked_components();
+    assert_eq!(components.len(), 3);
+    assert_eq!(components[0], admin.account);
+    let hello_components = components
+        .iter()
+        .filter(|&&component| test_env.component_blueprint(component).1 == BLUEPRINT)
+        .count();
+    assert_eq!(hello_components, 2);
+}
+
+#[test]
+fn test_call_method_ok() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let old_state: u32 = test_env.call_method_ok(hello_component, "update_state", params!(42u32));
+    assert_eq!(old_state, 0);
+    let old_state: u32 = test_env.call_method_ok(hello_component, "update_state", params!(7u32));
+    assert_eq!(old_state, 42);
+}
+
+#[test]
+fn test_alias_user() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let token = test_env.create_token(dec!("10000"));
+    let user = test_env.create_user("user");
+    test_env.alias_user("admin", "treasury_owner");
+
+    assert_eq!(*test_env.get_user("treasury_owner"), admin);
+    test_env.acting_as("user");
+    test_env.acting_as("treasury_owner");
+    let transfer_receipt = test_env.transfer_resource(dec!("10"), &token, &user);
+    assert!(transfer_receipt.result.is_ok());
+}
+
+#[test]
+fn test_receipt_history() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let user = test_env.create_user("user");
+    test_env.create_token(dec!("10000"));
+    assert!(test_env.receipts().is_empty());
+    assert!(test_env.last_receipt().is_none());
+
+    test_env.record_receipts();
+    let token = test_env.create_token(dec!("10000"));
+    test_env.transfer_resource(dec!("10"), &token, &user);
+
+    assert_eq!(test_env.receipts().len(), 2);
+    assert_eq!(test_env.receipts()[0].new_resource_addresses[0], token);
+    assert!(test_env.last_receipt().unwrap().result.is_ok());
+}
+
+#[test]
+fn test_transfer_all() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let user = test_env.create_user("user");
+    let token = test_env.create_token(dec!("10000"));
+
+    let transfer_receipt = test_env.transfer_all(&token, &user);
+    assert!(transfer_receipt.result.is_ok());
+    assert_eq!(test_env.get_amount_for_rd(admin.account, token), dec!("0"));
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, token),
+        dec!("10000")
+    );
+
+    // nothing left to transfer
+    let transfer_receipt = test_env.transfer_all(&token, &user);
+    assert!(transfer_receipt.result.is_ok());
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, token),
+        dec!("10000")
+    );
+}
+
+#[test]
+fn test_component_vaults() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let token = test_env.create_token(dec!("10000"));
+    let badge = test_env.create_badge(dec!("1"));
+
+    let vaults = test_env.component_vaults(admin.account);
+    assert_eq!(vaults.len(), 3);
+    assert!(vaults.contains(&(token, dec!("10000"))));
+    assert!(vaults.contains(&(badge, dec!("1"))));
+    assert!(vaults.iter().any(|(resource, _)| *resource == RADIX_TOKEN));
+}
+
+#[test]
+fn test_call_method_repeat() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let mut receipts =
+        test_env.call_method_repeat(hello_component, "update_state", params!(42u32), 3);
+    assert_eq!(receipts.len(), 3);
+    let old_states: Vec<u32> = receipts
+        .iter_mut()
+        .map(|receipt| return_of_call_method(receipt, "update_state"))
+        .collect();
+    assert_eq!(old_states, vec![0, 42, 42]);
+}
+
+#[test]
+fn test_decode_outputs() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+    test_env.call_method(hello_component, "update_state", params!(42u32));
+
+    let method_receipt = test_env.call_method(hello_component, "update_state", params!(7u32));
+    let outputs = decode_outputs(&method_receipt);
+    assert_eq!(outputs.len(), method_receipt.outputs.len());
+    assert!(outputs[0].contains("42"));
+}
+
+#[test]
+fn test_fee_payer() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let relayer = test_env.create_user("relayer");
+    let user = test_env.create_user("user");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    test_env.acting_as("user").set_fee_payer("relayer");
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+    assert_eq!(instantiate_receipt.validated_transaction.signers.len(), 2);
+
+    // returned resources still go to the acting user
+    let admin_badge = instantiate_receipt.new_resource_addresses[0];
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, admin_badge),
+        dec!("1")
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(relayer.account, admin_badge),
+        dec!("0")
+    );
+
+    test_env.clear_fee_payer();
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert_eq!(instantiate_receipt.validated_transaction.signers.len(), 1);
+}
+
+#[test]
+fn test_import_registry() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let mut other_ledger = InMemorySubstateStore::with_bootstrap();
+    let mut other_env = TestEnv::new(&mut other_ledger);
+    other_env.create_user("acc1");
+    other_env.publish_package(PACKAGE, &package);
+    other_env.import_registry(&test_env);
+
+    assert_eq!(*other_env.get_user("acc1"), user);
+    assert_eq!(
+        other_env.get_package(PACKAGE),
+        test_env.get_package(PACKAGE)
+    );
+    assert_eq!(other_env.get_current_user().0.account, user.account);
+
+    // the imported key signs for the imported account
+    other_env.acting_as("acc1");
+    let receipt = other_env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+    assert!(receipt.result.is_ok());
+}
+
+#[test]
+fn test_import_registry_extra_keys() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let (_, private_key) = test_env.executor.new_key_pair();
+    test_env.add_key_to_user("acc1", private_key);
+
+    let mut other_ledger = InMemorySubstateStore::with_bootstrap();
+    let mut other_env = TestEnv::new(&mut other_ledger);
+    other_env.create_user("acc1");
+    other_env.import_registry(&test_env);
+
+    // the imported account signs with all of its keys
+    let receipt = other_env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+    assert!(receipt.result.is_ok());
+    assert_eq!(receipt.validated_transaction.signers.len(), 2);
+}
+
+#[test]
+fn test_assert_balances_equal() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let alice = test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+    test_env.assert_balances_equal(alice.account, bob.account, RADIX_TOKEN);
+
+    let token = test_env.create_token(dec!("100"));
+    test_env.transfer_resource(dec!("50"), &token, &bob);
+    test_env.assert_balances_equal(alice.account, bob.account, token);
+}
+
+#[test]
+#[should_panic(expected = "Balances of")]
+fn test_assert_balances_equal_mismatch() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let alice = test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+    test_env.fund_xrd("bob", dec!("1"));
+    test_env.assert_balances_equal(alice.account, bob.account, RADIX_TOKEN);
+}
+
+#[test]
+fn test_publish_package_from_file() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    test_env.create_user("acc1");
+
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    let wasm_path = std::env::temp_dir().join("scrypto_unit_hello_world.wasm");
+    std::fs::write(&wasm_path, &package).unwrap();
+
+    test_env
+        .publish_package_from_file(PACKAGE, wasm_path.to_str().unwrap())
+        .unwrap();
+    let receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(receipt.result.is_ok());
+
+    let error = test_env
+        .publish_package_from_file("missing", "does/not/exist.wasm")
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    assert!(error.to_string().contains("does/not/exist.wasm"));
+}
+
+#[test]
+fn test_call_method_values() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let args = vec![scrypto::values::ScryptoValue::from_value(&7u32)];
+    let receipt = test_env.call_method_values(hello_component, "update_state", args);
+    assert!(receipt.result.is_ok());
+
+    let state: HelloState = test_env.component_state(hello_component);
+    assert_eq!(state.state, 7);
+}
+
+#[test]
+fn test_assert_xrd_conserved() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let alice = test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+    let carol = test_env.create_user("carol");
+    let accounts = [alice.account, bob.account, carol.account];
+
+    test_env.transfer_resource(dec!("250"), &RADIX_TOKEN, &bob);
+    test_env.acting_as("bob");
+    test_env.transfer_resource(dec!("100"), &RADIX_TOKEN, &carol);
+    test_env.assert_xrd_conserved(&accounts, dec!("3000000"));
+}
+
+#[test]
+fn test_blueprint_abi() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let package_address = test_env.get_package(PACKAGE);
+    let abi = test_env.blueprint_abi(package_address, BLUEPRINT);
+    assert_eq!(abi.blueprint_name, BLUEPRINT);
+    assert!(abi
+        .functions
+        .iter()
+        .any(|function| function.name == "instantiate"));
+    assert!(abi
+        .methods
+        .iter()
+        .any(|method| method.name == "protected_update_state"));
+}
+
+#[test]
+fn test_call_method_labeled() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let alice = test_env.create_user("alice");
+    test_env.record_receipts();
+
+    test_env.call_method_labeled(
+        "setup: alice balance",
+        alice.account,
+        "balance",
+        args![RADIX_TOKEN],
+    );
+    test_env.call_method(alice.account, "balance", args![RADIX_TOKEN]);
+
+    let labels: Vec<Option<&str>> = test_env
+        .receipt_history
+        .as_ref()
+        .unwrap()
+        .labeled()
+        .map(|(label, _)| label)
+        .collect();
+    assert_eq!(labels, vec![Some("setup: alice balance"), None]);
+}
+
+#[test]
+fn test_call_with_assertion() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let receipt = test_env.call_with_assertion(
+        hello_component,
+        "update_state",
+        params!(5u32),
+        (hello_component, "assert_state", params!(5u32)),
+    );
+    assert!(receipt.result.is_ok());
+
+    // a failed assertion rolls back the method call
+    let receipt = test_env.call_with_assertion(
+        hello_component,
+        "update_state",
+        params!(6u32),
+        (hello_component, "assert_state", params!(7u32)),
+    );
+    assert!(receipt.result.is_err());
+    let state: HelloState = test_env.component_state(hello_component);
+    assert_eq!(state.state, 5);
+}
+
+#[test]
+fn test_encode_non_fungible_address() {
+    let resource = RADIX_TOKEN;
+    let id = NonFungibleId::from_u32(42);
+
+    let encoded = encode_non_fungible_address(resource, id.clone());
+    let decoded: NonFungibleAddress = scrypto_decode(&encoded).unwrap();
+    assert_eq!(decoded.resource_address(), resource);
+    assert_eq!(decoded.non_fungible_id(), id);
+    assert_eq!(params!(decoded), vec![encoded]);
+}
+
+#[test]
+fn test_call_method_with_nonce() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+
+    let nonce = test_env.executor.get_nonce([user.key]);
+    let receipt = test_env
+        .call_method_with_nonce(user.account, "balance", args![RADIX_TOKEN], nonce)
+        .unwrap();
+    assert!(receipt.result.is_ok());
+
+    let receipt = test_env
+        .call_method_with_nonce(user.account, "balance", args![RADIX_TOKEN], nonce + 100)
+        .unwrap();
+    assert!(receipt.result.is_ok());
+}
+
+#[test]
+fn test_assert_state_delta() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    test_env.call_method(hello_component, "update_state", params!(10u32));
+    test_env.assert_state_delta(
+        hello_component,
+        |state: &HelloState| state.state as i64,
+        "update_state",
+        params!(4u32),
+        -6,
+    );
+}
+
+#[test]
+fn test_execute_manifest() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let manifest = format!(
+        "CALL_METHOD ComponentAddress(\"{}\") \"update_state\" 9u32;",
+        hello_component
+    );
+    let receipt = test_env.execute_manifest(&manifest);
+    assert!(receipt.result.is_ok());
+
+    let state: HelloState = test_env.component_state(hello_component);
+    assert_eq!(state.state, 9);
+}
+
+#[test]
+#[should_panic(expected = "Cannot compile the manifest")]
+fn test_execute_manifest_invalid() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    test_env.execute_manifest("NOT_AN_INSTRUCTION;");
+}
+
+#[test]
+fn test_all_resources() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let token = test_env.create_token(dec!("1000"));
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let admin_badge = instantiate_receipt.new_resource_addresses[0];
+
+    assert_eq!(
+        test_env.all_resources(),
+        vec![RADIX_TOKEN, token, admin_badge]
+    );
+}
+
+#[test]
+fn test_assert_created() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert_created(&instantiate_receipt, 1, 1);
+}
+
+#[test]
+#[should_panic(expected = "Expected 2 new resources")]
+fn test_assert_created_mismatch() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert_created(&instantiate_receipt, 1, 2);
+}
+
+#[test]
+fn test_call_method_with_returned_bucket() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let receipt = test_env.call_method_with_returned_bucket(
+        user.account,
+        "withdraw_by_amount",
+        args![dec!("250"), RADIX_TOKEN],
+        "deposit",
+        vec![],
+        RADIX_TOKEN,
+    );
+    assert!(receipt.result.is_ok());
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, RADIX_TOKEN),
+        dec!("1000000")
+    );
+
+    // nothing is repaid if the borrowed bucket holds another resource
+    let token = test_env.create_token(dec!("10"));
+    test_env.without_deposit();
+    let receipt = test_env.call_method_with_returned_bucket(
+        user.account,
+        "withdraw_by_amount",
+        args![dec!("5"), token],
+        "deposit",
+        vec![],
+        RADIX_TOKEN,
+    );
+    assert!(receipt.result.is_err());
+}
+
+#[test]
+fn test_can_withdraw() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let restricted = test_env.create_resource(|config| {
+        config
+            .restrict_withdraw(rule!(deny_all), LOCKED)
+            .initial_supply(dec!("100"))
+    });
+
+    assert!(test_env.can_withdraw("admin", RADIX_TOKEN, dec!("1000")));
+    assert!(!test_env.can_withdraw("admin", RADIX_TOKEN, dec!("1000001")));
+    assert!(!test_env.can_withdraw("admin", restricted, dec!("1")));
+
+    // balances are unchanged
+    assert_eq!(
+        test_env.get_amount_for_rd(admin.account, RADIX_TOKEN),
+        dec!("1000000")
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(admin.account, restricted),
+        dec!("100")
+    );
+}
+
+#[test]
+fn test_failed_instruction_index() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+    assert_eq!(failed_instruction_index(&instantiate_receipt), None);
+
+    // the assertion call is the second of three instructions, after the method call and
+    // before the deposit, so only the output of the first one is kept
+    let receipt = test_env.call_with_assertion(
+        hello_component,
+        "update_state",
+        params!(1u32),
+        (hello_component, "assert_state", params!(2u32)),
+    );
+    assert_eq!(receipt.validated_transaction.instructions.len(), 3);
+    assert_eq!(receipt.outputs.len(), 1);
+    assert_eq!(failed_instruction_index(&receipt), Some(1));
+    assert_instruction_failed(&receipt, 1);
+
+    // a failing first instruction has no outputs
+    let receipt = test_env.call_with_assertion(
+        hello_component,
+        "assert_state",
+        params!(2u32),
+        (hello_component, "assert_state", params!(1u32)),
+    );
+    assert_eq!(failed_instruction_index(&receipt), Some(0));
+}
+
+#[test]
+#[cfg(feature = "builtin-blueprints")]
+fn test_setup_treasury() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let treasury = test_env.setup_treasury();
+    assert_eq!(test_env.tracked_components(), vec![user.account, treasury]);
+
+    let receipt = test_env.call_method(treasury, "dispense", args![RADIX_TOKEN, dec!("500")]);
+    assert!(receipt.result.is_ok());
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, RADIX_TOKEN),
+        dec!("1000500")
+    );
+
+    let token = test_env.create_token(dec!("100"));
+    test_env.with_deposit_to(treasury);
+    test_env.call_method(user.account, "withdraw_by_amount", args![dec!("40"), token]);
+    let balance: Decimal = test_env.call_method_ok(treasury, "balance", args![token]);
+    assert_eq!(balance, dec!("40"));
+
+    // the treasury package is only published once
+    let transaction_count = test_env.transaction_count();
+    let other_treasury = test_env.setup_treasury();
+    assert_ne!(other_treasury, treasury);
+    assert_eq!(test_env.transaction_count(), transaction_count + 2);
+}
+
+#[test]
+fn test_receipt_epoch() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    test_env.record_receipts();
+
+    test_env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+    test_env.executor.substate_store_mut().set_epoch(5);
+    test_env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+
+    assert_eq!(test_env.receipt_epoch(0), Some(0));
+    assert_eq!(test_env.receipt_epoch(1), Some(5));
+    assert_eq!(test_env.receipt_epoch(2), None);
+}
+
+#[test]
+fn test_assert_empty() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let alice = test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+    let token = test_env.create_token(dec!("100"));
+    test_env.assert_empty(bob.account, token);
+
+    test_env.transfer_all(&token, &bob);
+    test_env.assert_empty(alice.account, token);
+}
+
+#[test]
+#[should_panic(expected = "but it holds 1")]
+fn test_assert_empty_leftover() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let alice = test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+    let token = test_env.create_token(dec!("100"));
+    test_env.transfer_resource(dec!("99"), &token, &bob);
+    test_env.assert_empty(alice.account, token);
+}
+
+#[test]
+fn test_registries_ordered_by_name() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("carol");
+    test_env.create_user("alice");
+    test_env.create_user("bob");
+
+    let names: Vec<&String> = test_env.users.keys().collect();
+    assert_eq!(names, vec!["alice", "bob", "carol"]);
+}
+
+#[derive(TypeId, Decode)]
+struct AccountState {
+    vaults: LazyMap<ResourceAddress, Vault>,
+}
+
+#[test]
+fn test_kv_store_get() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let token = test_env.create_token(dec!("100"));
+
+    let vault: Option<Vault> =
+        test_env.kv_store_get(user.account, |state: &AccountState| &state.vaults, &token);
+    assert!(vault.is_some());
+
+    let fresh = test_env.create_user("fresh");
+    let vault: Option<Vault> =
+        test_env.kv_store_get(fresh.account, |state: &AccountState| &state.vaults, &token);
+    assert!(vault.is_none());
+}
+
+#[test]
+fn test_assert_all_users_equal_xrd() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("alice");
+    test_env.create_user("bob");
+    test_env.create_user("carol");
+    test_env.assert_all_users_equal_xrd();
+}
+
+#[test]
+#[should_panic(expected = "User carol holds 1000010 XRD but alice holds 1000000 XRD")]
+fn test_assert_all_users_equal_xrd_mismatch() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("alice");
+    test_env.create_user("bob");
+    test_env.create_user("carol");
+    test_env.fund_xrd("carol", dec!("10"));
+    test_env.assert_all_users_equal_xrd();
+}
+
+#[test]
+fn test_remove_user_and_package() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let temp = test_env.create_user("temp");
+    test_env.alias_user("admin", "owner");
+
+    test_env.remove_user("temp");
+    assert!(test_env.try_get_user("temp").is_err());
+    assert!(!test_env.users_pk.contains_key(&temp.account));
+    assert_eq!(test_env.current_user, Some(admin));
+
+    // the key stays while an alias remains
+    test_env.remove_user("admin");
+    assert!(test_env.users_pk.contains_key(&admin.account));
+    assert_eq!(test_env.current_user, Some(admin));
+    test_env.remove_user("owner");
+    assert!(test_env.users.is_empty());
+    assert_eq!(test_env.current_user, None);
+
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+    test_env.remove_package(PACKAGE);
+    assert!(test_env.packages.is_empty());
+    assert_eq!(test_env.current_package, None);
+}
+
+#[test]
+fn test_call_method_with_bucket() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let other = test_env.create_user("acc2");
+    let token = test_env.create_token(dec!("100"));
+
+    let receipt =
+        test_env.call_method_with_bucket(other.account, "deposit", token, dec!("30"), vec![]);
+    assert!(receipt.result.is_ok());
+
+    assert_eq!(test_env.get_amount_for_rd(other.account, token), dec!("30"));
+    assert_eq!(test_env.get_amount_for_rd(user.account, token), dec!("70"));
+}
+
+#[test]
+fn test_call_method_with_proof() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let mut receipt = test_env.call_method_with_proof(
+        hello_component,
+        "proof_amount",
+        RADIX_TOKEN,
+        dec!("25"),
+        vec![],
+    );
+    let amount: Decimal = return_of_call_method(&mut receipt, "proof_amount");
+    assert_eq!(amount, dec!("25"));
+
+    let ids: BTreeSet<NonFungibleId> = (1..=2).map(NonFungibleId::from_u32).collect();
+    let characters = test_env.create_resource(|config| {
+        config.non_fungible().initial_non_fungible_supply(
+            ids.iter()
+                .map(|id| {
+                    (
+                        id.clone(),
+                        Character {
+                            name: "Hero".to_owned(),
+                            level: 1,
+                        },
+                    )
+                })
+                .collect(),
+        )
+    });
+    let mut receipt = test_env.call_method_with_proof_by_ids(
+        hello_component,
+        "proof_amount",
+        characters,
+        &ids,
+        vec![],
+    );
+    let amount: Decimal = return_of_call_method(&mut receipt, "proof_amount");
+    assert_eq!(amount, dec!("2"));
+}
+
+#[test]
+fn test_timed_call_method() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let (receipt, duration) =
+        test_env.timed_call_method(user.account, "balance", args![RADIX_TOKEN]);
+    assert!(receipt.result.is_ok());
+    assert!(duration > std::time::Duration::from_secs(0));
+}
+
+#[test]
+fn test_assert_indivisible() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let admin_badge = instantiate_receipt.new_resource_addresses[0];
+    test_env.assert_indivisible(admin_badge);
+}
+
+#[test]
+#[should_panic(expected = "is divisible")]
+fn test_assert_indivisible_divisible() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    test_env.assert_indivisible(RADIX_TOKEN);
+}
+
+#[test]
+#[should_panic(expected = "the withdrawal failed")]
+fn test_assert_indivisible_other_failure() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let restricted = test_env.create_resource(|config| {
+        config
+            .divisibility(DIVISIBILITY_NONE)
+            .restrict_withdraw(rule!(deny_all), LOCKED)
+            .initial_supply(dec!("1"))
+    });
+    test_env.assert_indivisible(restricted);
+}
+
+#[test]
+fn test_portfolio() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+    let token = test_env.create_token(dec!("100"));
+    test_env.transfer_resource(dec!("40"), &token, &bob);
+
+    let portfolio = test_env.portfolio();
+    assert_eq!(portfolio.len(), 2);
+    assert_eq!(portfolio[&token], dec!("60"));
+
+    test_env.acting_as("bob");
+    let portfolio = test_env.portfolio();
+    assert_eq!(portfolio[&RADIX_TOKEN], dec!("1000000"));
+    assert_eq!(portfolio[&token], dec!("40"));
+}
+
+#[test]
+fn test_add_key_to_user() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    test_env.create_user("acc2");
+    let (extra_key, extra_private_key) = test_env.executor.new_key_pair();
+    test_env.add_key_to_user("acc1", extra_private_key);
+
+    let receipt = test_env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+    assert!(receipt.result.is_ok());
+    assert_eq!(
+        receipt.validated_transaction.signers,
+        vec![user.key, extra_key]
+    );
+
+    // the extra key only signs for its user
+    test_env.acting_as("acc2");
+    let receipt = test_env.call_method(user.account, "balance", args![RADIX_TOKEN]);
+    assert_eq!(receipt.validated_transaction.signers.len(), 1);
+}
+
+#[test]
+fn test_add_key_to_user_multisig_account() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let bob = test_env.create_user("bob");
+    let (key_a, private_key_a) = test_env.executor.new_key_pair();
+    let (key_b, private_key_b) = test_env.executor.new_key_pair();
+    let withdraw_rule = rule!(
+        require(NonFungibleAddress::from_public_key(&key_a))
+            && require(NonFungibleAddress::from_public_key(&key_b))
+    );
+    let receipt = test_env.execute_custom(|builder| {
+        builder
+            .call_method(SYSTEM_COMPONENT, "free_xrd", vec![])
+            .take_from_worktop(RADIX_TOKEN, |builder, bucket_id| {
+                builder.new_account_with_resource(&withdraw_rule, bucket_id)
+            })
+    });
+    let multisig = User {
+        key: key_a,
+        account: receipt.new_component_addresses[0],
+    };
+    test_env.users.insert(String::from("multisig"), multisig);
+    test_env.users_pk.insert(multisig.account, private_key_a);
+    test_env.acting_as("multisig");
+
+    // a single signature doesn't meet the withdraw rule
+    let receipt = test_env.transfer_resource(dec!("10"), &RADIX_TOKEN, &bob);
+    assert!(receipt.result.is_err());
+    assert!(!test_env.can_withdraw("multisig", RADIX_TOKEN, dec!("10")));
+
+    test_env.add_key_to_user("multisig", private_key_b);
+    let receipt = test_env.transfer_resource(dec!("10"), &RADIX_TOKEN, &bob);
+    assert!(receipt.result.is_ok());
+    assert_eq!(
+        test_env.get_amount_for_rd(bob.account, RADIX_TOKEN),
+        dec!("1000010")
+    );
+    assert!(test_env.can_withdraw("multisig", RADIX_TOKEN, dec!("10")));
+}
+
+#[test]
+fn test_assert_rejected_and_runtime_failure() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+
+    let result = test_env.call_method_unsigned(user.account, "balance", args![RADIX_TOKEN]);
+    assert_rejected(&result);
+
+    let receipt = test_env.call_method(user.account, "no_such_method", vec![]);
+    assert_runtime_failure(&receipt);
+}
+
+#[test]
+#[should_panic(expected = "but it failed at runtime")]
+fn test_assert_rejected_runtime_failure() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let result = test_env.try_call_method(user.account, "no_such_method", vec![]);
+    assert_rejected(&result);
+}
+
+#[test]
+fn test_call_method_expecting_worktop() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let treasury = test_env.create_user("treasury");
+
+    let receipt = test_env.call_method_expecting_worktop(
+        user.account,
+        "withdraw_by_amount",
+        args![dec!("10"), RADIX_TOKEN],
+        vec![(RADIX_TOKEN, dec!("10"))],
+    );
+    assert!(receipt.result.is_ok());
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, RADIX_TOKEN),
+        dec!("1000000")
+    );
+
+    test_env.with_deposit_to(treasury.account);
+    test_env.call_method_expecting_worktop(
+        user.account,
+        "withdraw_by_amount",
+        args![dec!("10"), RADIX_TOKEN],
+        vec![(RADIX_TOKEN, dec!("10"))],
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(treasury.account, RADIX_TOKEN),
+        dec!("1000010")
+    );
+}
+
+#[test]
+#[should_panic(expected = "Expected exactly 4 of")]
+fn test_call_method_expecting_worktop_leftover() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    // more than expected is left over
+    let user = test_env.create_user("acc1");
+    test_env.call_method_expecting_worktop(
+        user.account,
+        "withdraw_by_amount",
+        args![dec!("10"), RADIX_TOKEN],
+        vec![(RADIX_TOKEN, dec!("4"))],
+    );
+}
+
+#[test]
+#[should_panic(expected = "Expected exactly 11 of")]
+fn test_call_method_expecting_worktop_short() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    // less than expected is returned
+    let user = test_env.create_user("acc1");
+    test_env.call_method_expecting_worktop(
+        user.account,
+        "withdraw_by_amount",
+        args![dec!("10"), RADIX_TOKEN],
+        vec![(RADIX_TOKEN, dec!("11"))],
+    );
+}
+
+#[test]
+#[should_panic(expected = "without a deposit")]
+fn test_call_method_expecting_worktop_without_deposit() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    test_env.without_deposit();
+    test_env.call_method_expecting_worktop(
+        user.account,
+        "withdraw_by_amount",
+        args![dec!("10"), RADIX_TOKEN],
+        vec![(RADIX_TOKEN, dec!("10"))],
+    );
+}
+
+#[test]
+fn test_assert_public_method() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    test_env.assert_public_method(hello_component, "update_state", params!(3u32));
+    assert_eq!(test_env.current_user, Some(admin));
+    assert_eq!(test_env.users.len(), 1);
+    assert_eq!(test_env.users_pk.len(), 1);
+    assert_eq!(
+        test_env.tracked_components(),
+        vec![admin.account, hello_component]
+    );
+
+    // the current user is restored when the call panics, here on invalid arguments
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        test_env.assert_public_method(hello_component, "update_state", vec![vec![255u8]]);
+    }));
+    assert!(result.is_err());
+    assert_eq!(test_env.current_user, Some(admin));
+    assert_eq!(test_env.users_pk.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "is not public")]
+fn test_assert_public_method_protected() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    test_env.assert_public_method(hello_component, "protected_update_state", params!(3u32));
+}
+
+#[test]
+fn test_create_token_pair() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let (token_a, token_b) = test_env.create_token_pair(dec!("1000"), dec!("250"));
+
+    assert_ne!(token_a, token_b);
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, token_a),
+        dec!("1000")
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, token_b),
+        dec!("250")
+    );
+}
+
+#[test]
+fn test_assert_approx_eq() {
+    assert_approx_eq(dec!("10"), dec!("10"), dec!("0"));
+    assert_approx_eq(dec!("9.95"), dec!("10"), dec!("0.05"));
+    assert_approx_eq(dec!("10.05"), dec!("10"), dec!("0.05"));
+}
+
+#[test]
+#[should_panic(expected = "the difference is 0.06")]
+fn test_assert_approx_eq_out_of_tolerance() {
+    assert_approx_eq(dec!("10.06"), dec!("10"), dec!("0.05"));
+}
+
+#[test]
+fn test_publish_package_as() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    test_env.record_receipts();
+
+    test_env.create_user("admin");
+    let publisher = test_env.create_user("publisher");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package_as("publisher", PACKAGE, &package);
+
+    let receipt = test_env.last_receipt().unwrap();
+    assert_eq!(receipt.validated_transaction.signers, vec![publisher.key]);
+    assert_eq!(
+        test_env.get_package(PACKAGE),
+        receipt.new_package_addresses[0]
+    );
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    assert!(instantiate_receipt.result.is_ok());
+}
+
+#[test]
+fn test_assert_nf_data_eq() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let id = NonFungibleId::from_u32(1);
+    let hero = || Character {
+        name: "Hero".to_owned(),
+        level: 3,
+    };
+    let characters = test_env.create_resource(|config| {
+        config
+            .non_fungible()
+            .initial_non_fungible_supply(vec![(id.clone(), hero())])
+    });
+
+    test_env.assert_nf_data_eq(characters, &id, hero());
+}
+
+#[test]
+#[should_panic(expected = "doesn't match")]
+fn test_assert_nf_data_eq_mismatch() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let id = NonFungibleId::from_u32(1);
+    let characters = test_env.create_resource(|config| {
+        config.non_fungible().initial_non_fungible_supply(vec![(
+            id.clone(),
+            Character {
+                name: "Hero".to_owned(),
+                level: 3,
+            },
+        )])
+    });
+
+    test_env.assert_nf_data_eq(
+        characters,
+        &id,
+        Character {
+            name: "Hero".to_owned(),
+            level: 4,
+        },
+    );
+}
+
+#[test]
+fn test_create_user_with_key() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let private_key = EcdsaPrivateKey::from_bytes(&[7u8; 32]).unwrap();
+    let public_key = private_key.public_key();
+    let user = test_env.create_user_with_key("imported", public_key, private_key);
+
+    assert_eq!(*test_env.get_user("imported"), user);
+    assert_eq!(test_env.current_user, Some(user));
+    assert!(test_env.tracked_components().contains(&user.account));
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, RADIX_TOKEN),
+        dec!("1000000")
+    );
+
+    // the imported key signs for the account
+    let other = test_env.create_user("other");
+    let receipt = test_env.transfer_resource(dec!("10"), &RADIX_TOKEN, &other);
+    assert!(receipt.result.is_ok());
+}
+
+#[test]
+fn test_execute_custom() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let alice = test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+
+    // leftovers go back to the current user
+    let receipt = test_env.execute_custom(|builder| {
+        builder.withdraw_from_account_by_amount(dec!("10"), RADIX_TOKEN, alice.account)
+    });
+    assert!(receipt.result.is_ok());
+    assert_eq!(
+        test_env.get_amount_for_rd(alice.account, RADIX_TOKEN),
+        dec!("1000000")
+    );
+
+    // unless the manifest deposits them
+    let receipt = test_env.execute_custom(|builder| {
+        builder
+            .withdraw_from_account_by_amount(dec!("10"), RADIX_TOKEN, alice.account)
+            .deposit_all_to(bob.account)
+    });
+    assert!(receipt.result.is_ok());
+    assert_eq!(
+        test_env.get_amount_for_rd(bob.account, RADIX_TOKEN),
+        dec!("1000010")
+    );
+}
+
+#[test]
+fn test_advance_and_call() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    test_env.record_receipts();
+
+    test_env.advance_epoch(3);
+    test_env.advance_and_call(4, user.account, "balance", args![RADIX_TOKEN]);
+
+    assert_eq!(test_env.executor.substate_store().get_epoch(), 7);
+    assert_eq!(test_env.receipt_epoch(0), Some(7));
+}
+
+#[test]
+fn test_transaction_count() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    assert_eq!(test_env.transaction_count(), 0);
+
+    let user = test_env.create_user("acc1");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+    assert_eq!(test_env.transaction_count(), 2);
+
+    test_env.create_token(dec!("100"));
+    assert_eq!(test_env.transaction_count(), 3);
+
+    // rejected transactions are not counted
+    let result = test_env.call_method_unsigned(user.account, "balance", args![RADIX_TOKEN]);
+    assert!(result.is_err());
+    assert_eq!(test_env.transaction_count(), 3);
+}
+
+#[test]
+fn test_assert_portfolios_equal() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let alice = test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+    let token = test_env.create_token(dec!("100"));
+    test_env.transfer_resource(dec!("30"), &token, &alice);
+    test_env.transfer_resource(dec!("30"), &token, &bob);
+
+    test_env.assert_portfolios_equal(alice.account, bob.account);
+}
+
+#[test]
+#[should_panic(expected = "Portfolios differ")]
+fn test_assert_portfolios_equal_mismatch() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let alice = test_env.create_user("alice");
+    let bob = test_env.create_user("bob");
+    test_env.create_token(dec!("100"));
+
+    test_env.assert_portfolios_equal(alice.account, bob.account);
+}
+
+#[test]
+fn test_for_each_executor() {
+    let mut runs = 0;
+    for_each_executor!(|env| {
+        let user = env.create_user("acc1");
+        let token = env.create_token(dec!("100"));
+        assert_eq!(env.get_amount_for_rd(user.account, token), dec!("100"));
+        runs += 1;
+    });
+    assert_eq!(runs, 2);
+}
+
+#[test]
+#[should_panic(expected = "Failed with tracing = false: Expected 100 tokens")]
+fn test_for_each_executor_failure() {
+    for_each_executor!(|env| {
+        let user = env.create_user("acc1");
+        let token = env.create_token(dec!("10"));
+        let amount = env.get_amount_for_rd(user.account, token);
+        assert!(amount == dec!("100"), "Expected 100 tokens, got {}", amount);
+    });
+}
+
+#[test]
+fn test_assert_panic_message() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    test_env.create_user("acc1");
+
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let instantiate_receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = instantiate_receipt.new_component_addresses[0];
+
+    let receipt = test_env.call_method(hello_component, "assert_state", params!(1u32));
+    assert_panic_message(&receipt, "Unexpected state");
+}
+
+#[test]
+#[should_panic(expected = "Expected a panic with")]
+fn test_assert_panic_message_mismatch() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    let user = test_env.create_user("acc1");
+
+    let receipt = test_env.call_method(user.account, "no_such_method", vec![]);
+    assert_panic_message(&receipt, "Unexpected state");
+}
+
+#[test]
+fn test_create_voters() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let dao = test_env.create_user("dao");
+    let token = test_env.create_token(dec!("1000"));
+
+    let voters = test_env.create_voters(token, &[("alice", dec!("100")), ("bob", dec!("250"))]);
+    assert_eq!(voters.len(), 2);
+    assert_eq!(voters[0], *test_env.get_user("alice"));
+    assert_eq!(
+        test_env.get_amount_for_rd(voters[0].account, token),
+        dec!("100")
+    );
+    assert_eq!(
+        test_env.get_amount_for_rd(voters[1].account, token),
+        dec!("250")
+    );
+    assert_eq!(test_env.get_amount_for_rd(dao.account, token), dec!("650"));
+}
+
+#[test]
+#[should_panic(expected = "required")]
+fn test_create_voters_insufficient_stake() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("dao");
+    let token = test_env.create_token(dec!("100"));
+    test_env.create_voters(token, &[("alice", dec!("60")), ("bob", dec!("60"))]);
+}
+
+#[test]
+fn test_ensure_ready() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    assert_eq!(test_env.ensure_ready(), Err(TestEnvError::NoCurrentUser));
+
+    test_env.create_user("acc1");
+    assert_eq!(test_env.ensure_ready(), Err(TestEnvError::NoCurrentPackage));
+
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package("hello-world", &package);
+    assert_eq!(test_env.ensure_ready(), Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "no current user set; call create_user or acting_as first")]
+fn test_call_function_without_user() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.call_function("Hello", "instantiate", vec![]);
+}
+
+#[test]
+fn test_instantiate_capturing() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    test_env.create_user("acc1");
+
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package("hello-world", &package);
+
+    let (component, deposited, receipt) =
+        test_env.instantiate_capturing("Hello", "instantiate", vec![]);
+    assert_eq!(component, receipt.new_component_addresses[0]);
+    assert_eq!(
+        deposited,
+        vec![(receipt.new_resource_addresses[0], dec!("1"))]
+    );
+}
+
+#[test]
+fn test_instantiate_capturing_with_deposit_target() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    test_env.create_user("acc1");
+    let treasury = test_env.create_user("treasury");
+
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let (_, deposited, receipt) = test_env
+        .with_deposit_to(treasury.account)
+        .instantiate_capturing(BLUEPRINT, "instantiate", vec![]);
+    let admin_badge = receipt.new_resource_addresses[0];
+    assert_eq!(deposited, vec![(admin_badge, dec!("1"))]);
+    assert_eq!(
+        test_env.get_amount_for_rd(treasury.account, admin_badge),
+        dec!("1")
+    );
+}
+
+#[test]
+fn test_access_rules() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+    test_env.create_user("acc1");
+
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = receipt.new_component_addresses[0];
+
+    let rules = test_env.access_rules(hello_component);
+    assert_eq!(rules["update_state"], "AllowAll");
+    assert_eq!(rules["assert_state"], "AllowAll");
+    assert!(rules["protected_update_state"].starts_with("Protected"));
+    assert!(!rules.contains_key("instantiate"));
+}
+
+#[test]
+fn test_debug_replay() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.record_transactions();
+    test_env.create_user("admin");
+    let user = test_env.create_user("user");
+    let token = test_env.create_token(dec!("10000"));
+    test_env.transfer_resource(dec!("10"), &token, &user);
+    let log = test_env.transaction_log.clone().unwrap();
+
+    let mut other_ledger = InMemorySubstateStore::with_bootstrap();
+    let mut other_env = TestEnv::new(&mut other_ledger);
+    other_env.create_user("admin");
+    other_env.create_user("user");
+    other_env.record_transactions();
+
+    // only the traced transaction is recorded and counted
+    let receipt = other_env.debug_replay(&log, 1);
+    assert!(receipt.result.is_ok());
+    assert_eq!(other_env.transaction_count(), 3);
+    assert_eq!(other_env.transaction_log.as_ref().unwrap().len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "debug_replay needs a test environment that has executed no transaction")]
+fn test_debug_replay_into_recording_env() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.record_transactions();
+    test_env.create_user("admin");
+    test_env.create_token(dec!("10000"));
+    test_env.create_token(dec!("10"));
+    let log = test_env.transaction_log.clone().unwrap();
+
+    test_env.debug_replay(&log, 1);
+}
+
+#[test]
+#[should_panic(expected = "No transaction 2 in a log of 2 transactions")]
+fn test_debug_replay_out_of_range() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.record_transactions();
+    test_env.create_user("admin");
+    test_env.create_token(dec!("10000"));
+    test_env.create_token(dec!("10"));
+    let log = test_env.transaction_log.clone().unwrap();
+
+    test_env.debug_replay(&log, 2);
+}
+
+#[test]
+fn test_system_resources() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    assert_eq!(test_env.xrd(), RADIX_TOKEN);
+    assert_eq!(test_env.ecdsa_badge(), ECDSA_TOKEN);
+    assert_eq!(test_env.system_badge(), SYSTEM_TOKEN);
+
+    let user = test_env.create_user("acc1");
+    let xrd = test_env.xrd();
+    assert_eq!(
+        test_env.get_amount_for_rd(user.account, xrd),
+        dec!("1000000")
+    );
+}
+
+#[test]
+fn test_assert_nf_count() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let player = test_env.create_user("player");
+    let characters = test_env.create_resource(|config| {
+        config.non_fungible().initial_non_fungible_supply(vec![
+            (
+                NonFungibleId::from_u32(1),
+                Character {
+                    name: "Hero".to_owned(),
+                    level: 1,
+                },
+            ),
+            (
+                NonFungibleId::from_u32(2),
+                Character {
+                    name: "Sidekick".to_owned(),
+                    level: 1,
+                },
+            ),
+        ])
+    });
+
+    test_env.assert_nf_count(admin.account, characters, 2);
+    test_env.assert_nf_count(player.account, characters, 0);
+}
+
+#[test]
+#[should_panic(expected = "is not a non-fungible resource")]
+fn test_assert_nf_count_fungible() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let admin = test_env.create_user("admin");
+    let token = test_env.create_token(dec!("2"));
+    test_env.assert_nf_count(admin.account, token, 2);
+}
+
+#[test]
+fn test_assert_component_idempotent() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = receipt.new_component_addresses[0];
+
+    let receipt =
+        test_env.assert_component_idempotent(hello_component, "update_state", params!(3u32));
+    let old_state: u32 = scrypto_decode(&receipt.outputs[0].raw[..]).unwrap();
+    assert_eq!(old_state, 3);
+}
+
+#[test]
+#[cfg(feature = "builtin-blueprints")]
+#[should_panic(expected = "again changed its vaults")]
+fn test_assert_component_idempotent_changed() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let treasury = test_env.setup_treasury();
+    test_env.assert_component_idempotent(treasury, "dispense", args![RADIX_TOKEN, dec!("10")]);
+}
+
+#[test]
+fn test_call_method_capture_returns() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let other = test_env.create_user("acc2");
+    let token = test_env.create_token(dec!("100"));
+
+    let (receipt, returned) = test_env
+        .with_deposit_to(other.account)
+        .call_method_capture_returns(
+            user.account,
+            "withdraw_by_amount",
+            args![dec!("500"), RADIX_TOKEN],
+        );
+    assert!(receipt.result.is_ok());
+    assert_eq!(returned, vec![(RADIX_TOKEN, dec!("500"))]);
+    assert_eq!(
+        test_env.get_amount_for_rd(other.account, RADIX_TOKEN),
+        dec!("1000500")
+    );
+
+    let (_, returned) = test_env
+        .with_deposit_to(other.account)
+        .call_method_capture_returns(user.account, "withdraw_by_amount", args![dec!("20"), token]);
+    assert_eq!(returned, vec![(token, dec!("20"))]);
+
+    // a method returning nothing
+    let (receipt, returned) =
+        test_env.call_method_capture_returns(user.account, "balance", args![RADIX_TOKEN]);
+    assert!(receipt.result.is_ok());
+    assert!(returned.is_empty());
+
+    // a resource created outside of the helpers is not tracked, but is still reported
+    let transaction = TransactionBuilder::new()
+        .new_token_fixed(HashMap::new(), dec!("100"))
+        .call_method_with_all_resources(user.account, "deposit_batch")
+        .build(test_env.executor.get_nonce([user.key]))
+        .sign([&test_env.users_pk[&user.account]]);
+    let receipt = test_env
+        .executor
+        .validate_and_execute(&transaction)
+        .unwrap();
+    let untracked = receipt.new_resource_addresses[0];
+    assert!(!test_env.all_resources().contains(&untracked));
+    let (_, returned) = test_env
+        .with_deposit_to(other.account)
+        .call_method_capture_returns(
+            user.account,
+            "withdraw_by_amount",
+            args![dec!("5"), untracked],
+        );
+    assert_eq!(returned, vec![(untracked, dec!("5"))]);
+
+    // the bucket withdrawn from the target account is netted out
+    let (receipt, returned) = test_env.call_method_capture_returns(
+        user.account,
+        "withdraw_by_amount",
+        args![dec!("10"), RADIX_TOKEN],
+    );
+    assert!(receipt.result.is_ok());
+    assert!(returned.is_empty());
+}
+
+#[test]
+fn test_did_succeed() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let package = compile_package!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/assets/hello-world/",
+    ));
+    test_env.publish_package(PACKAGE, &package);
+
+    let receipt = test_env.call_function(BLUEPRINT, "instantiate", vec![]);
+    let hello_component = receipt.new_component_addresses[0];
+
+    assert!(test_env.did_succeed(hello_component, "update_state", params!(3u32)));
+    assert!(test_env.did_succeed(hello_component, "assert_state", params!(3u32)));
+    assert!(!test_env.did_succeed(hello_component, "assert_state", params!(4u32)));
+    assert!(!test_env.did_succeed(hello_component, "protected_update_state", params!(4u32)));
+}
+
+#[test]
+#[cfg(feature = "builtin-blueprints")]
+fn test_set_resource_metadata() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    test_env.create_user("admin");
+    let badge = test_env.create_badge(dec!("1"));
+    let other_badge = test_env.create_badge(dec!("1"));
+    let token = test_env.create_resource(|config| {
+        config
+            .metadata("name", "Test token")
+            .updateable_metadata(rule!(require(badge)), LOCKED)
+            .initial_supply(dec!("100"))
+    });
+
+    let receipt =
+        test_env.set_resource_metadata(token, "icon_url", "https://example.com/a.png", badge);
+    assert!(receipt.result.is_ok());
+    let metadata = test_env.resource_metadata(token);
+    assert_eq!(metadata["icon_url"], "https://example.com/a.png");
+    assert_eq!(metadata["name"], "Test token");
+
+    // the metadata package is only published by the first call
+    let transaction_count = test_env.transaction_count();
+    let receipt =
+        test_env.set_resource_metadata(token, "icon_url", "https://example.com/b.png", other_badge);
+    assert!(receipt.result.is_err());
+    assert_eq!(test_env.transaction_count(), transaction_count + 1);
+    assert_eq!(
+        test_env.resource_metadata(token)["icon_url"],
+        "https://example.com/a.png"
+    );
+}
+
+#[test]
+fn test_component_holds() {
+    let mut ledger = InMemorySubstateStore::with_bootstrap();
+    let mut test_env = TestEnv::new(&mut ledger);
+
+    let user = test_env.create_user("acc1");
+    let other = test_env.create_user("acc2");
+    let token = test_env.create_token(dec!("100"));
+    assert_eq!(
+        test_env.component_holds(other.account, token),
+        Decimal::zero()
+    );
+
+    test_env.with_deposit_to(other.account);
+    test_env.call_method(user.account, "withdraw_by_amount", args![dec!("40"), token]);
+    assert_eq!(test_env.component_holds(other.account, token), dec!("40"));
+    assert_eq!(test_env.component_holds(user.account, token), dec!("60"));
+}